@@ -2,6 +2,8 @@ if get(g:, 'LanguageClient_loaded')
     finish
 endif
 
+let s:enabled = 0
+
 let s:TYPE = {
 \   'string':  type(''),
 \   'list':    type([]),
@@ -132,6 +134,18 @@ function! s:getInput(prompt, default) abort
     return l:input
 endfunction
 
+" Returns 0-based index of selected option, -1 if cancelled.
+function! s:inputList(prompt, options) abort
+    let l:selections = [a:prompt] + map(copy(a:options), { key, val -> printf('%d) %s', key + 1, val ) })
+    call inputsave()
+    let l:selection = inputlist(l:selections)
+    call inputrestore()
+    if l:selection < 1 || l:selection > len(a:options)
+        return -1
+    endif
+    return l:selection - 1
+endfunction
+
 function! s:FZF(source, sink) abort
     if !get(g:, 'loaded_fzf')
         call s:Echoerr('FZF not loaded!')
@@ -155,22 +169,69 @@ function! s:FZF(source, sink) abort
     endif
 endfunction
 
+" Open path with action. Returns 1 if user cancelled, 0 otherwise.
 function! s:Edit(action, path) abort
     " If editing current file, push current location to jump list.
     let l:bufnr = bufnr(a:path)
     if l:bufnr == bufnr('%')
         execute 'normal m`'
-        return
+        return 0
     endif
 
     let l:action = a:action
-    " Avoid the 'not saved' warning.
-    if l:action ==# 'edit' && l:bufnr != -1
-        execute 'buffer' l:bufnr
-        return
+    if l:action is v:null
+        let l:action = get(g:, 'LanguageClient_locationOpenCommand', 'edit')
+    endif
+
+    " Reuse window already showing the file, preferably in current tab page.
+    if l:action ==# 'drop' && l:bufnr != -1
+        let l:winids = win_findbuf(l:bufnr)
+        if !empty(l:winids)
+            let l:tabwinids = filter(copy(l:winids), 'win_id2tabwin(v:val)[0] == tabpagenr()')
+            call win_gotoid(get(l:tabwinids, 0, l:winids[0]))
+            return 0
+        endif
     endif
 
-    execute l:action . ' ' . fnameescape(a:path)
+    " Failures, e.g., E37 or a declined swap file (E325), are returned as message, so that they
+    " aren't taken for cancel.
+    try
+        if l:action ==# 'edit' && !s:CanAbandonBuffer()
+            let l:choice = confirm('Save changes to "' . expand('%:t') . '"?',
+                        \ "&Yes\n&No, open in split\n&Cancel", 1)
+            if l:choice == 1
+                write
+            elseif l:choice == 2
+                let l:action = 'split'
+            else
+                return 1
+            endif
+        endif
+
+        " Avoid the 'not saved' warning.
+        if l:action ==# 'edit' && l:bufnr != -1
+            execute 'buffer' l:bufnr
+            return 0
+        endif
+
+        execute l:action . ' ' . fnameescape(a:path)
+    catch
+        return v:exception
+    endtry
+    return 0
+endfunction
+
+" Whether current buffer can be left for another in current window, honoring
+" 'hidden' and 'autowrite'. Modified buffer is written if 'autowrite' is set.
+function! s:CanAbandonBuffer() abort
+    if !&modified || &hidden || &bufhidden =~# 'hide' || len(win_findbuf(bufnr('%'))) > 1
+        return 1
+    endif
+    if (&autowrite || &autowriteall) && &buftype ==# '' && !&readonly
+        silent write
+        return 1
+    endif
+    return 0
 endfunction
 
 " Batch version of `matchdelete()`.
@@ -180,6 +241,22 @@ function! s:MatchDelete(ids) abort
     endfor
 endfunction
 
+let s:diagnosticsLocationListTitle = 'LanguageClient diagnostics'
+
+" Set location lists of windows, given as [winid, entries] pairs, to
+" diagnostics of their buffers. Unless live, lists set otherwise, e.g., with
+" :lgrep, are kept, see g:LanguageClient_diagnosticsListLive.
+function! s:SetDiagnosticsLocationLists(lists, live) abort
+    for [l:winid, l:entries] in a:lists
+        if !a:live && !empty(getloclist(l:winid))
+                    \ && getloclist(l:winid, {'title': 0}).title !=# s:diagnosticsLocationListTitle
+            continue
+        endif
+        call setloclist(l:winid, l:entries, 'r')
+        call setloclist(l:winid, [], 'a', {'title': s:diagnosticsLocationListTitle})
+    endfor
+endfunction
+
 " Batch version of nvim_buf_add_highlight
 function! s:AddHighlights(source, highlights) abort
     for hl in a:highlights
@@ -326,8 +403,14 @@ function! s:HandleOutput(output, ...) abort
     elseif has_key(a:output, 'error')
         let l:error = get(a:output, 'error')
         let l:message = get(l:error, 'message')
+        let l:data = get(l:error, 'data', v:null)
+        let l:kind = type(l:data) == s:TYPE.dict ? get(l:data, 'kind', '') : ''
         if !l:quiet
-            call s:Echoerr(l:message)
+            if l:kind ==# 'ServerNotRunning' || l:kind ==# 'RpcTimeout'
+                call s:Echowarn(l:message)
+            else
+                call s:Echoerr(l:message)
+            endif
         endif
         return v:null
     else
@@ -593,6 +676,18 @@ function! LanguageClient#textDocument_formatting(...) abort
     return LanguageClient#Call('textDocument/formatting', l:params, l:Callback)
 endfunction
 
+function! LanguageClient#previewFormatting(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/previewFormatting', l:params, l:Callback)
+endfunction
+
 function! LanguageClient#textDocument_rangeFormatting(...) abort
     let l:Callback = get(a:000, 1, v:null)
     let l:params = {
@@ -618,6 +713,15 @@ function! LanguageClient#completionItem_resolve(completion_item, ...) abort
     return LanguageClient#Call('completionItem/resolve', l:params, l:Callback)
 endfunction
 
+function! LanguageClient#formatChangedRanges(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/formatChangedRanges', l:params, l:Callback)
+endfunction
+
 function! LanguageClient#textDocument_rangeFormatting_sync(...) abort
     let l:result = LanguageClient_runSync('LanguageClient#textDocument_rangeFormatting', {
                 \ 'handle': v:true,
@@ -663,6 +767,13 @@ function! LanguageClient#textDocument_didClose() abort
                 \ })
 endfunction
 
+function! LanguageClient#filterReferences(filter, ...) abort
+    let l:Callback = get(a:000, 0, v:null)
+    return LanguageClient#Call('languageClient/filterReferences', {
+                \ 'filter': a:filter,
+                \ }, l:Callback)
+endfunction
+
 function! LanguageClient#textDocument_documentHighlight(...) abort
     let l:Callback = get(a:000, 1, v:null)
     let l:params = {
@@ -680,6 +791,221 @@ function! LanguageClient#clearDocumentHighlight() abort
     return LanguageClient#Notify('languageClient/clearDocumentHighlight', {})
 endfunction
 
+function! LanguageClient#workspaceOutline(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'handle': s:IsFalse(l:Callback),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/workspaceOutline', l:params, l:Callback)
+endfunction
+
+let s:workspaceOutlineBufname = '__LanguageClient_Outline__'
+
+" Show workspace outline in a side window, lines indented by nesting, with
+" [filename, line, col] of each line. When refreshing, outline is only updated
+" if shown, and focus stays in current window.
+function! s:ShowWorkspaceOutline(lines, locations, refresh) abort
+    let l:winnr = bufwinnr(s:workspaceOutlineBufname)
+    if a:refresh && l:winnr == -1
+        return
+    endif
+
+    let l:winid = win_getid()
+    if l:winnr == -1
+        execute 'silent keepalt botright vertical 40split' s:workspaceOutlineBufname
+        setlocal buftype=nofile bufhidden=hide noswapfile nobuflisted nonumber nowrap
+        setlocal foldmethod=indent shiftwidth=2 foldlevel=99
+        nnoremap <buffer> <silent> <CR> :call <SID>WorkspaceOutlineJump()<CR>
+    else
+        execute l:winnr . 'wincmd w'
+    endif
+
+    let l:view = winsaveview()
+    setlocal modifiable
+    silent %delete _
+    call setline(1, a:lines)
+    setlocal nomodifiable
+    let b:LanguageClient_outlineLocations = a:locations
+    call winrestview(l:view)
+    if a:refresh
+        call win_gotoid(l:winid)
+    endif
+endfunction
+
+function! s:WorkspaceOutlineJump() abort
+    let l:location = get(b:LanguageClient_outlineLocations, line('.') - 1, v:null)
+    if l:location is v:null
+        return
+    endif
+    wincmd p
+    let l:result = s:Edit(v:null, l:location[0])
+    if type(l:result) == s:TYPE.string
+        call s:Echoerr(l:result)
+    elseif l:result == 0
+        call cursor(l:location[1], l:location[2])
+    endif
+endfunction
+
+function! s:RefreshWorkspaceOutline() abort
+    if &buftype !=# '' || &filetype ==# '' || bufwinnr(s:workspaceOutlineBufname) == -1
+        return
+    endif
+    call LanguageClient#workspaceOutline({'refresh': v:true})
+endfunction
+
+function! LanguageClient#textDocument_inlineCompletion(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ 'handle': s:IsFalse(l:Callback),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('textDocument/inlineCompletion', l:params, l:Callback)
+endfunction
+
+function! LanguageClient#acceptInlineCompletion(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/acceptInlineCompletion', l:params, l:Callback)
+endfunction
+
+" Keys accepting inline completion shown as ghost text, for an insert mode
+" <expr> mapping. Returns a:1, if given, when there is none.
+function! LanguageClient#acceptInlineCompletionKeys(...) abort
+    let l:fallback = get(a:000, 0, '')
+    if empty(s:inlineCompletion)
+        return l:fallback
+    endif
+
+    let l:result = LanguageClient_runSync('LanguageClient#acceptInlineCompletion', {})
+    if type(l:result) != type({})
+        return l:fallback
+    endif
+    let s:inlineCompletionText = l:result.text
+    " Inserted literally, so that indentation of following lines is kept as is.
+    return repeat("\<Del>", l:result.delete)
+                \ . "\<C-r>\<C-o>=LanguageClient#inlineCompletionText()\<CR>"
+endfunction
+
+" Text of inline completion being accepted, see
+" LanguageClient#acceptInlineCompletionKeys().
+function! LanguageClient#inlineCompletionText() abort
+    return s:inlineCompletionText
+endfunction
+
+" Hide inline completion. Returns empty string, so that it can be used in an
+" insert mode <expr> mapping.
+function! LanguageClient#dismissInlineCompletion() abort
+    if !empty(s:inlineCompletion)
+        call s:ClearInlineCompletion()
+        call LanguageClient#Notify('languageClient/dismissInlineCompletion', {})
+    endif
+    return ''
+endfunction
+
+" Keep code lenses of buffer, as [line, title] pairs, in
+" b:LanguageClient_codeLenses, and show them as virtual text on neovim.
+function! s:SetCodeLenses(filename, lenses) abort
+    let l:bufnr = bufnr(a:filename)
+    if l:bufnr == -1
+        return
+    endif
+    call setbufvar(l:bufnr, 'LanguageClient_codeLenses', a:lenses)
+    if !exists('*nvim_buf_set_virtual_text')
+        return
+    endif
+    if !exists('s:codeLensNamespace')
+        let s:codeLensNamespace = nvim_create_namespace('LanguageClientCodeLens')
+    endif
+    call nvim_buf_clear_namespace(l:bufnr, s:codeLensNamespace, 0, -1)
+    for [l:line, l:title] in a:lenses
+        call nvim_buf_set_virtual_text(l:bufnr, s:codeLensNamespace, l:line,
+                    \ [[l:title, 'Comment']], {})
+    endfor
+endfunction
+
+let s:inlineCompletion = {}
+let s:inlineCompletionText = ''
+let s:inlineCompletionTimer = -1
+
+" Show inline completion after cursor, as virtual text on neovim, or echoed
+" otherwise. Only first line of multiline suggestions is shown.
+function! s:ShowInlineCompletion(line, text) abort
+    call s:ClearInlineCompletion()
+    let l:lines = split(a:text, "\n", 1)
+    let l:text = l:lines[0] . (len(l:lines) > 1 ? ' …' : '')
+    let s:inlineCompletion = {
+                \ 'bufnr': bufnr('%'),
+                \ 'line': a:line,
+                \ 'col': col('.'),
+                \ }
+    if exists('*nvim_buf_set_virtual_text')
+        if !exists('s:inlineCompletionNamespace')
+            let s:inlineCompletionNamespace = nvim_create_namespace('LanguageClientInlineCompletion')
+        endif
+        call nvim_buf_set_virtual_text(0, s:inlineCompletionNamespace, a:line,
+                    \ [[l:text, 'Comment']], {})
+    else
+        echohl Comment | echo l:text | echohl None
+    endif
+endfunction
+
+function! s:ClearInlineCompletion() abort
+    if empty(s:inlineCompletion)
+        return
+    endif
+    if exists('s:inlineCompletionNamespace')
+        if bufexists(s:inlineCompletion.bufnr)
+            call nvim_buf_clear_namespace(s:inlineCompletion.bufnr,
+                        \ s:inlineCompletionNamespace, 0, -1)
+        endif
+    else
+        echo ''
+    endif
+    let s:inlineCompletion = {}
+endfunction
+
+" Request inline completion shortly after typing stops, see
+" g:LanguageClient_inlineCompletion.
+function! s:HandleTextChangedIInlineCompletion() abort
+    if !get(g:, 'LanguageClient_inlineCompletion', 0) || !exists('*timer_start')
+        return
+    endif
+    call timer_stop(s:inlineCompletionTimer)
+    let s:inlineCompletionTimer = timer_start(
+                \ get(g:, 'LanguageClient_inlineCompletionDelay', 200),
+                \ function('s:InlineCompletionTimer'))
+endfunction
+
+function! s:InlineCompletionTimer(timer) abort
+    if mode() !=# 'i' || pumvisible() || &buftype !=# '' || &filetype ==# ''
+        return
+    endif
+    call LanguageClient#textDocument_inlineCompletion({'triggerKind': 2})
+endfunction
+
+function! s:HandleCursorMovedIInlineCompletion() abort
+    if empty(s:inlineCompletion)
+        return
+    endif
+    if s:inlineCompletion.bufnr != bufnr('%')
+                \ || s:inlineCompletion.line != line('.') - 1
+                \ || s:inlineCompletion.col != col('.')
+        call LanguageClient#dismissInlineCompletion()
+    endif
+endfunction
+
 function! LanguageClient#getState(callback) abort
     return LanguageClient#Call('languageClient/getState', {}, a:callback)
 endfunction
@@ -688,7 +1014,50 @@ function! LanguageClient#isAlive(callback) abort
     return LanguageClient#Call('languageClient/isAlive', {}, a:callback)
 endfunction
 
+" Set up autocmds. Returns 1 if not done before.
+function! LanguageClient#enable() abort
+    if s:enabled
+        return 0
+    endif
+    let s:enabled = 1
+
+    augroup languageClient
+        autocmd BufNewFile * call LanguageClient#handleBufNewFile()
+        autocmd BufReadPost * call LanguageClient#handleBufReadPost()
+        autocmd BufWritePre * call LanguageClient#handleBufWritePre()
+        autocmd BufWritePost * call LanguageClient#handleBufWritePost()
+        autocmd BufDelete * call LanguageClient#handleBufDelete()
+        autocmd TextChanged * call LanguageClient#handleTextChanged()
+        if exists('##OptionSet')
+            " Entering or leaving diff mode suspends or resumes diagnostics.
+            autocmd OptionSet diff call LanguageClient#handleTextChanged()
+        endif
+        autocmd TextChangedI * call LanguageClient#handleTextChanged()
+        if exists('##TextChangedP')
+            autocmd TextChangedP * call LanguageClient#handleTextChanged()
+        endif
+        autocmd InsertCharPre * call s:HandleInsertCharPre()
+        autocmd TextChangedI * call s:AutoComplete()
+        autocmd InsertLeave * let s:autoCompletePending = 0
+        autocmd TextChangedI * call s:HandleTextChangedIInlineCompletion()
+        autocmd CursorMovedI * call s:HandleCursorMovedIInlineCompletion()
+        autocmd InsertLeave * call LanguageClient#dismissInlineCompletion()
+        autocmd BufReadPost,BufWritePost,TextChanged,InsertLeave *
+                    \ call s:RefreshWorkspaceOutline()
+        autocmd CursorMoved * call LanguageClient#handleCursorMoved()
+        autocmd CursorHold * call LanguageClient#handleCursorHold()
+        autocmd VimLeavePre * call LanguageClient#handleVimLeavePre()
+        autocmd CompleteDone * call LanguageClient#handleCompleteDone()
+        if get(g:, 'LanguageClient_signatureHelpOnCompleteDone', 0)
+            autocmd CompleteDone *
+                        \ call LanguageClient#textDocument_signatureHelp({}, 's:HandleOutputNothing')
+        endif
+    augroup END
+    return 1
+endfunction
+
 function! LanguageClient#startServer(...) abort
+    call LanguageClient#enable()
     let l:params = {
                 \ 'filename': LSP#filename(),
                 \ 'cmdargs': [],
@@ -698,6 +1067,7 @@ function! LanguageClient#startServer(...) abort
 endfunction
 
 function! LanguageClient#registerServerCommands(cmds, ...) abort
+    call LanguageClient#enable()
     let l:handle = a:0 > 0 ? a:1 : v:null
     return LanguageClient#Call('languageClient/registerServerCommands', a:cmds, l:handle, v:true)
 endfunction
@@ -721,6 +1091,11 @@ function! LanguageClient#registerHandlers(handlers, ...) abort
     return LanguageClient#Call('languageClient/registerHandlers', a:handlers, l:handle)
 endfunction
 
+function! LanguageClient#registerUriHandlers(handlers, ...) abort
+    let l:handle = a:0 > 0 ? a:1 : v:null
+    return LanguageClient#Call('languageClient/registerUriHandlers', a:handlers, l:handle)
+endfunction
+
 function! s:ExecuteAutocmd(event) abort
     if exists('#User#' . a:event)
         execute 'doautocmd <nomodeline> User ' . a:event
@@ -781,6 +1156,42 @@ function! LanguageClient#handleTextChanged() abort
     endtry
 endfunction
 
+function! LanguageClient#handleBufWritePre() abort
+    if &buftype !=# '' || &filetype ==# '' || empty(get(g:, 'LanguageClient_changedRangesOnSave', []))
+        return
+    endif
+
+    try
+        call LanguageClient_runSync('LanguageClient#formatChangedRanges', {})
+    catch
+        call s:Debug('LanguageClient caught exception: ' . string(v:exception))
+    endtry
+endfunction
+
+" Parse lines of external command output into quickfix entries with filename,
+" see g:LanguageClient_saveCommands. Relative filenames are resolved against
+" root, the directory command was run in.
+function! LanguageClient#parseErrorformat(lines, errorformat, root) abort
+    let l:efm = a:errorformat is v:null ? &errorformat : a:errorformat
+    " :cd drops window local directory, so restore the same kind of directory.
+    let l:cd = haslocaldir() ? 'lcd' : 'cd'
+    let l:cwd = getcwd()
+    try
+        execute 'lcd' fnameescape(a:root)
+        let l:items = getqflist({'lines': a:lines, 'efm': l:efm}).items
+        call filter(l:items, 'v:val.valid && v:val.bufnr > 0')
+        return map(l:items, '{
+                    \ "filename": fnamemodify(bufname(v:val.bufnr), ":p"),
+                    \ "lnum": v:val.lnum,
+                    \ "col": v:val.col,
+                    \ "text": v:val.text,
+                    \ "type": empty(v:val.type) ? v:null : v:val.type,
+                    \ }')
+    finally
+        execute l:cd fnameescape(l:cwd)
+    endtry
+endfunction
+
 function! LanguageClient#handleBufWritePost() abort
     if &buftype !=# '' || &filetype ==# ''
         return
@@ -796,13 +1207,18 @@ function! LanguageClient#handleBufWritePost() abort
 endfunction
 
 function! LanguageClient#handleBufDelete() abort
-    if &buftype !=# '' || &filetype ==# ''
+    " Buffer being deleted is not necessarily the current one.
+    let l:bufnr = str2nr(expand('<abuf>'))
+    let l:filetype = getbufvar(l:bufnr, '&filetype')
+    if getbufvar(l:bufnr, '&buftype') !=# '' || l:filetype ==# ''
         return
     endif
 
     try
         call LanguageClient#Notify('languageClient/handleBufDelete', {
-                    \ 'filename': LSP#filename(),
+                    \ 'buftype': '',
+                    \ 'languageId': l:filetype,
+                    \ 'filename': fnamemodify(bufname(l:bufnr), ':p'),
                     \ })
     catch
         call s:Debug('LanguageClient caught exception: ' . string(v:exception))
@@ -834,6 +1250,21 @@ function! LanguageClient#handleCursorMoved() abort
     endtry
 endfunction
 
+function! LanguageClient#handleCursorHold() abort
+    if &buftype !=# '' || &filetype ==# ''
+        return
+    endif
+
+    try
+        call LanguageClient#Notify('languageClient/handleCursorHold', {
+                    \ 'buftype': &buftype,
+                    \ 'filename': LSP#filename(),
+                    \ })
+    catch
+        call s:Debug('LanguageClient caught exception: ' . string(v:exception))
+    endtry
+endfunction
+
 function! LanguageClient#handleCompleteDone() abort
     let user_data = get(v:completed_item, 'user_data', '')
     if user_data ==# ''
@@ -959,6 +1390,51 @@ function! LanguageClient#complete(findstart, base) abort
     endif
 endfunction
 
+" Automatic completion, see g:LanguageClient_autoComplete. Completion can't be started in
+" InsertCharPre, due to textlock. It's marked pending there, and started once the character is
+" inserted.
+let s:autoCompletePending = 0
+
+function! s:HandleInsertCharPre() abort
+    let l:setting = get(g:, 'LanguageClient_autoComplete', {})
+    let l:minChars = get(l:setting, &filetype, get(l:setting, '*', -1))
+    if l:minChars < 0 || &omnifunc !=# 'LanguageClient#complete' || &paste || pumvisible()
+        return
+    endif
+    " Popup menu would be missing on replay, making recorded keys to select from it misbehave.
+    if exists('*reg_executing') && (reg_recording() !=# '' || reg_executing() !=# '')
+        return
+    endif
+
+    let l:input = strpart(getline('.'), 0, col('.') - 1) . v:char
+    for l:trigger in get(b:, 'LanguageClient_triggerCharacters', [])
+        if l:trigger !=# '' && l:input[-len(l:trigger):] ==# l:trigger
+            let s:autoCompletePending = 1
+            return
+        endif
+    endfor
+    if l:minChars > 0 && v:char =~# '\k'
+        let s:autoCompletePending = len(matchstr(l:input, '\k*$')) == l:minChars
+    endif
+endfunction
+
+function! s:AutoComplete() abort
+    if !s:autoCompletePending
+        return
+    endif
+    let s:autoCompletePending = 0
+    if mode() !=# 'i' || pumvisible()
+        return
+    endif
+
+    let l:keys = "\<C-x>\<C-o>"
+    if &completeopt !~# 'noinsert\|noselect'
+        " Keep what's typed, instead of inserting the first item.
+        let l:keys .= "\<C-p>"
+    endif
+    call feedkeys(l:keys, 'n')
+endfunction
+
 function! LanguageClient#textDocument_signatureHelp(...) abort
     if &buftype !=# '' || &filetype ==# ''
         return
@@ -1019,6 +1495,14 @@ function! LanguageClient#serverStatusMessage() abort
     return g:LanguageClient_serverStatusMessage
 endfunction
 
+" List of running language server instances. Each has 'languageId', 'rootPath'
+" and 'pid'.
+let g:LanguageClient_serverInstances = []
+
+function! LanguageClient#serverInstances() abort
+    return g:LanguageClient_serverInstances
+endfunction
+
 " Example function usable for status line.
 function! LanguageClient#statusLine() abort
     if g:LanguageClient_serverStatusMessage ==# ''
@@ -1115,4 +1599,436 @@ function! LanguageClient#debugInfo(...) abort
     return LanguageClient#Call('languageClient/debugInfo', l:params, l:Callback)
 endfunction
 
+function! LanguageClient#memoryUsage(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'handle': s:IsFalse(l:Callback),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/memoryUsage', l:params, l:Callback)
+endfunction
+
+function! LanguageClient#metrics(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = get(a:000, 0, {})
+    return LanguageClient#Call('languageClient/metrics', l:params, l:Callback)
+endfunction
+
+function! LanguageClient#verifyServerCommand(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = get(a:000, 0, {})
+    return LanguageClient#Call('languageClient/verifyServerCommand', l:params, l:Callback)
+endfunction
+
+" Language servers LanguageClient#setup() looks for, in order of preference.
+let s:knownServers = [
+            \ {'filetypes': ['rust'], 'command': ['rls']},
+            \ {'filetypes': ['rust'], 'command': ['rust-analyzer']},
+            \ {'filetypes': ['python'], 'command': ['pyls']},
+            \ {'filetypes': ['go'], 'command': ['gopls']},
+            \ {'filetypes': ['go'], 'command': ['go-langserver']},
+            \ {'filetypes': ['c', 'cpp'], 'command': ['clangd']},
+            \ {'filetypes': ['c', 'cpp'], 'command': ['cquery']},
+            \ {'filetypes': ['javascript', 'javascript.jsx', 'typescript', 'typescript.tsx'],
+            \  'command': ['typescript-language-server', '--stdio']},
+            \ {'filetypes': ['javascript', 'javascript.jsx', 'typescript', 'typescript.tsx'],
+            \  'command': ['javascript-typescript-stdio']},
+            \ {'filetypes': ['sh'], 'command': ['bash-language-server', 'start']},
+            \ {'filetypes': ['ruby'], 'command': ['solargraph', 'stdio']},
+            \ {'filetypes': ['css', 'scss', 'less'], 'command': ['css-languageserver', '--stdio']},
+            \ {'filetypes': ['html'], 'command': ['html-languageserver', '--stdio']},
+            \ {'filetypes': ['json'], 'command': ['vscode-json-languageserver', '--stdio']},
+            \ {'filetypes': ['yaml'], 'command': ['yaml-language-server', '--stdio']},
+            \ {'filetypes': ['dockerfile'], 'command': ['docker-langserver', '--stdio']},
+            \ {'filetypes': ['haskell'], 'command': ['hie-wrapper']},
+            \ ]
+
+" Guided setup: detect known language servers on $PATH, ask which to use for
+" languages not configured yet, check each answers initialize request, and
+" write them to g:LanguageClient_serverCommandsFile. Returns server commands
+" added.
+function! LanguageClient#setup() abort
+    let l:configured = get(g:, 'LanguageClient_serverCommands', {})
+    let l:groups = {}
+    let l:languages = []
+    for l:server in s:knownServers
+        if !executable(l:server.command[0])
+                    \ || !empty(filter(copy(l:server.filetypes), 'has_key(l:configured, v:val)'))
+            continue
+        endif
+        let l:language = join(l:server.filetypes, ', ')
+        if !has_key(l:groups, l:language)
+            let l:groups[l:language] = []
+            call add(l:languages, l:language)
+        endif
+        call add(l:groups[l:language], l:server)
+    endfor
+    if empty(l:languages)
+        call s:Echowarn('LanguageClient: No language server found on $PATH for languages not '
+                    \ . 'configured yet. See g:LanguageClient_serverCommands.')
+        return {}
+    endif
+
+    let l:commands = {}
+    for l:language in l:languages
+        let l:candidates = l:groups[l:language]
+        let l:options = map(copy(l:candidates), 'join(v:val.command)') + ['Skip']
+        let l:index = s:inputList('Language server for ' . l:language . ':', l:options)
+        redraw
+        if l:index < 0 || l:index >= len(l:candidates)
+            continue
+        endif
+
+        let l:server = l:candidates[l:index]
+        echo 'Checking ' . join(l:server.command) . '...'
+        let l:result = LanguageClient_runSync('LanguageClient#verifyServerCommand', {
+                    \ 'command': l:server.command,
+                    \ })
+        redraw
+        if type(l:result) != type({}) || !l:result.ok
+            call s:Echoerr('LanguageClient: ' . join(l:server.command) . ' is not working: '
+                        \ . get(type(l:result) == type({}) ? l:result : {}, 'message', ''))
+            continue
+        endif
+        call s:Echomsg('LanguageClient: ' . l:result.message . ' is working.')
+        for l:filetype in l:server.filetypes
+            let l:commands[l:filetype] = l:server.command
+        endfor
+    endfor
+    if empty(l:commands)
+        return {}
+    endif
+
+    let l:path = expand(get(g:, 'LanguageClient_serverCommandsFile',
+                \ '~/.config/LanguageClient/serverCommands.json'))
+    let l:saved = filereadable(l:path) ? json_decode(join(readfile(l:path), "\n")) : {}
+    if !isdirectory(fnamemodify(l:path, ':h'))
+        call mkdir(fnamemodify(l:path, ':h'), 'p')
+    endif
+    call writefile([json_encode(extend(l:saved, l:commands))], l:path)
+    call s:Echomsg('LanguageClient: Server commands written to ' . l:path . '.')
+
+    call LanguageClient#registerServerCommands(l:commands)
+    if LanguageClient#enable() || has_key(l:commands, &filetype)
+        call LanguageClient#handleBufReadPost()
+    endif
+    return l:commands
+endfunction
+
+" Tasks, i.e., long-running commands like builds or test runs, keyed by id.
+let s:tasks = {}
+let s:task_id = 0
+
+function! s:RunTask(title, command, cwd, env) abort
+    let s:task_id += 1
+    let l:id = s:task_id
+    let l:task = {
+                \ 'id': l:id,
+                \ 'title': a:title,
+                \ 'command': a:command,
+                \ 'output': [],
+                \ 'cancelled': 0,
+                \ }
+    let l:options = {'cwd': a:cwd is v:null ? getcwd() : a:cwd}
+    if !empty(a:env)
+        let l:options.env = a:env
+    endif
+    if has('nvim')
+        let l:task.job = jobstart(a:command, extend(l:options, {
+                    \ 'on_stdout': function('s:HandleTaskOutput', [l:id]),
+                    \ 'on_stderr': function('s:HandleTaskOutput', [l:id]),
+                    \ 'on_exit': function('s:HandleTaskExit', [l:id]),
+                    \ }))
+    else
+        " Output may still be buffered when exit_cb is invoked, close_cb comes after all of it.
+        let l:task.job = job_start(a:command, extend(l:options, {
+                    \ 'out_cb': function('s:HandleTaskOutputVim', [l:id]),
+                    \ 'err_cb': function('s:HandleTaskOutputVim', [l:id]),
+                    \ 'close_cb': function('s:HandleTaskCloseVim', [l:id]),
+                    \ }))
+    endif
+    let s:tasks[l:id] = l:task
+    echomsg printf('LanguageClient: task %d started: %s', l:id, a:title)
+endfunction
+
+function! s:HandleTaskOutput(id, job, data, event) abort
+    if has_key(s:tasks, a:id)
+        call extend(s:tasks[a:id].output, filter(copy(a:data), 'v:val !=# ""'))
+    endif
+endfunction
+
+function! s:HandleTaskOutputVim(id, channel, message) abort
+    if has_key(s:tasks, a:id)
+        call add(s:tasks[a:id].output, a:message)
+    endif
+endfunction
+
+function! s:HandleTaskCloseVim(id, channel) abort
+    let l:job = ch_getjob(a:channel)
+    " Job might not be reaped yet right after its channel is closed.
+    let l:tries = 0
+    while job_status(l:job) ==# 'run' && l:tries < 100
+        sleep 10m
+        let l:tries += 1
+    endwhile
+    call s:HandleTaskExit(a:id, l:job, job_info(l:job).exitval)
+endfunction
+
+function! s:HandleTaskExit(id, job, status, ...) abort
+    if !has_key(s:tasks, a:id)
+        return
+    endif
+
+    let l:task = remove(s:tasks, a:id)
+    let l:efm = get(g:, 'LanguageClient_taskErrorformat', &errorformat)
+    call setqflist([], ' ', {
+                \ 'title': l:task.title,
+                \ 'lines': l:task.output,
+                \ 'efm': l:efm,
+                \ })
+    let g:LanguageClient_lastTask = {
+                \ 'id': l:task.id,
+                \ 'title': l:task.title,
+                \ 'command': l:task.command,
+                \ 'status': a:status,
+                \ 'cancelled': l:task.cancelled,
+                \ }
+    if l:task.cancelled
+        let l:result = 'cancelled'
+    elseif a:status == 0
+        let l:result = 'succeeded'
+    else
+        let l:result = 'failed with status ' . a:status
+    endif
+    echomsg printf('LanguageClient: task %d %s: %s', l:task.id, l:result, l:task.title)
+    call s:ExecuteAutocmd('LanguageClientTaskFinished')
+endfunction
+
+function! LanguageClient#tasks() abort
+    return map(values(s:tasks), '{"id": v:val.id, "title": v:val.title, "command": v:val.command}')
+endfunction
+
+function! LanguageClient#cancelTask(...) abort
+    let l:ids = a:0 > 0 ? [string(a:1)] : keys(s:tasks)
+    for l:id in l:ids
+        if !has_key(s:tasks, l:id)
+            continue
+        endif
+        let s:tasks[l:id].cancelled = 1
+        if has('nvim')
+            call jobstop(s:tasks[l:id].job)
+        else
+            call job_stop(s:tasks[l:id].job)
+        endif
+    endfor
+endfunction
+
+" Show formatted lines in a scratch buffer next to current buffer, in diff mode.
+function! s:PreviewFormatting(lines) abort
+    let l:filetype = &filetype
+    diffthis
+    vnew
+    setlocal buftype=nofile bufhidden=wipe noswapfile nobuflisted
+    call setline(1, a:lines)
+    let &l:filetype = l:filetype
+    setlocal nomodifiable
+    silent! file [LanguageClient\ formatting\ preview]
+    diffthis
+    autocmd BufWipeout <buffer> diffoff!
+endfunction
+
+" Briefly highlight positions, as accepted by `matchaddpos()`, in current window.
+function! s:FlashRanges(positions, group, duration) abort
+    if !exists('*timer_start')
+        return
+    endif
+
+    let l:ids = []
+    for l:pos in a:positions
+        call add(l:ids, matchaddpos(a:group, [l:pos]))
+    endfor
+    call timer_start(a:duration, function('s:FlashClear', [l:ids]))
+endfunction
+
+function! s:FlashClear(ids, timer) abort
+    for l:id in a:ids
+        " Window might have changed in the meanwhile.
+        silent! call matchdelete(l:id)
+    endfor
+endfunction
+
+function! LanguageClient#repeatLastCodeAction(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/repeatLastCodeAction', l:params, l:Callback)
+endfunction
+
+" Set omnifunc of current buffer, remembering previous value so that it can be
+" restored once language server is stopped.
+function! s:SetOmnifunc() abort
+    if !get(g:, 'LanguageClient_setOmnifunc', 1) || &omnifunc ==# 'LanguageClient#complete'
+        return
+    endif
+
+    " Warned once per buffer, not again when language server is restarted.
+    if &omnifunc !=# '' && !get(b:, 'LanguageClient_omnifuncWarned', 0)
+        " Replacing omnifunc from filetype plugins shipped with vim is expected.
+        let l:lastset = matchstr(execute('verbose setlocal omnifunc?'), 'Last set from \zs.\{-}\ze\( line \d\+\)\?\n\?$')
+        if l:lastset !=# ''
+            let l:lastset = fnamemodify(expand(l:lastset), ':p')
+        endif
+        if l:lastset ==# '' || stridx(l:lastset, fnamemodify($VIMRUNTIME, ':p')) != 0
+            let b:LanguageClient_omnifuncWarned = 1
+            call s:Echowarn('LanguageClient: replacing omnifunc ' . &omnifunc
+                        \ . (l:lastset ==# '' ? '' : ' set from ' . l:lastset)
+                        \ . '. Set g:LanguageClient_setOmnifunc to 0 to keep it.')
+        endif
+    endif
+
+    let b:LanguageClient_previousOmnifunc = &omnifunc
+    setlocal omnifunc=LanguageClient#complete
+endfunction
+
+function! s:RestoreOmnifunc(filetype) abort
+    for l:bufnr in range(1, bufnr('$'))
+        let l:bufvars = getbufvar(l:bufnr, '')
+        if type(l:bufvars) != s:TYPE.dict
+                    \ || getbufvar(l:bufnr, '&filetype') !=# a:filetype
+                    \ || !has_key(l:bufvars, 'LanguageClient_previousOmnifunc')
+            continue
+        endif
+
+        if getbufvar(l:bufnr, '&omnifunc') ==# 'LanguageClient#complete'
+            call setbufvar(l:bufnr, '&omnifunc', l:bufvars.LanguageClient_previousOmnifunc)
+        endif
+        call remove(l:bufvars, 'LanguageClient_previousOmnifunc')
+    endfor
+endfunction
+
+function! LanguageClient#cancelProgress(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'token': get(a:000, 0, v:null),
+                \ }
+    return LanguageClient#Call('languageClient/cancelProgress', l:params, l:Callback)
+endfunction
+
+function! LanguageClient#compareCapabilities(...) abort
+    let l:Callback = get(a:000, 1, v:null)
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    return LanguageClient#Call('languageClient/compareCapabilities', l:params, l:Callback)
+endfunction
+
+function! LanguageClient#tags(...) abort
+    let l:params = {
+                \ 'filename': LSP#filename(),
+                \ 'text': LSP#text(),
+                \ 'line': LSP#line(),
+                \ 'character': LSP#character(),
+                \ }
+    call extend(l:params, get(a:000, 0, {}))
+    let l:Callback = get(a:000, 1, v:null)
+    return LanguageClient#Call('languageClient/tags', l:params, l:Callback)
+endfunction
+
+" Usage: set tagfunc=LanguageClient#tagfunc
+function! LanguageClient#tagfunc(pattern, flags, info) abort
+    if &buftype !=# '' || !has_key(get(g:, 'LanguageClient_serverCommands', {}), &filetype)
+        return v:null
+    endif
+
+    let l:result = LanguageClient_runSync('LanguageClient#tags', {
+                \ 'pattern': a:pattern,
+                \ 'flags': a:flags,
+                \ })
+    " Fallback to tags files.
+    return empty(l:result) ? v:null : l:result
+endfunction
+
+" Params of position in arbitrary file, line and col counting from 1.
+" Params of a position in a file, empty if it can't be read.
+function! s:PositionParams(path, line, col) abort
+    let l:filename = fnamemodify(a:path, ':p')
+    let l:bufnr = bufnr(l:filename)
+    if !bufloaded(l:bufnr) && !filereadable(l:filename)
+        call s:Echoerr('LanguageClient: cannot read ' . l:filename)
+        return {}
+    endif
+    " Loading the buffer detects filetype of the file.
+    if !bufloaded(l:bufnr) && exists('*bufload')
+        let l:bufnr = bufadd(l:filename)
+        call bufload(l:bufnr)
+    endif
+    if bufloaded(l:bufnr)
+        let l:text = getbufline(l:bufnr, 1, '$')
+        let l:languageId = getbufvar(l:bufnr, '&filetype')
+        let l:fixendofline = getbufvar(l:bufnr, '&fixendofline')
+    else
+        let l:text = readfile(l:filename)
+        let l:languageId = &filetype
+        let l:fixendofline = 1
+    endif
+    if !empty(l:text) && l:text[-1] !=# '' && l:fixendofline
+        let l:text += ['']
+    endif
+
+    return {
+                \ 'buftype': '',
+                \ 'languageId': l:languageId,
+                \ 'filename': l:filename,
+                \ 'text': l:text,
+                \ 'line': a:line - 1,
+                \ 'character': a:col - 1,
+                \ }
+endfunction
+
+function! LanguageClient#hoverAt(path, line, col, ...) abort
+    let l:params = s:PositionParams(a:path, a:line, a:col)
+    if empty(l:params)
+        return v:null
+    endif
+    return LanguageClient#textDocument_hover(l:params, get(a:000, 0, v:null))
+endfunction
+
+function! LanguageClient#definitionAt(path, line, col, ...) abort
+    let l:params = s:PositionParams(a:path, a:line, a:col)
+    if empty(l:params)
+        return v:null
+    endif
+    return LanguageClient#textDocument_definition(l:params, get(a:000, 0, v:null))
+endfunction
+
+function! LanguageClient#typeDefinitionAt(path, line, col, ...) abort
+    let l:params = s:PositionParams(a:path, a:line, a:col)
+    if empty(l:params)
+        return v:null
+    endif
+    return LanguageClient#textDocument_typeDefinition(l:params, get(a:000, 0, v:null))
+endfunction
+
+function! LanguageClient#implementationAt(path, line, col, ...) abort
+    let l:params = s:PositionParams(a:path, a:line, a:col)
+    if empty(l:params)
+        return v:null
+    endif
+    return LanguageClient#textDocument_implementation(l:params, get(a:000, 0, v:null))
+endfunction
+
+function! LanguageClient#referencesAt(path, line, col, ...) abort
+    let l:params = s:PositionParams(a:path, a:line, a:col)
+    if empty(l:params)
+        return v:null
+    endif
+    return LanguageClient#textDocument_references(l:params, get(a:000, 0, v:null))
+endfunction
+
 let g:LanguageClient_loaded = s:Launch()
@@ -1,4 +1,6 @@
+use std::env;
 use std::fs::read_to_string;
+use std::process::Command;
 
 fn main() {
     let git_hash = option_env!("TRAVIS_COMMIT")
@@ -6,4 +8,17 @@ fn main() {
         .unwrap_or_else(|| read_to_string(".git/refs/heads/next").unwrap_or_default());
 
     println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+
+    // CommandExt::raw_arg on Windows is available since Rust 1.62.
+    println!("cargo:rustc-check-cfg=cfg(has_raw_arg)");
+    if rustc_minor_version().unwrap_or(0) >= 62 {
+        println!("cargo:rustc-cfg=has_raw_arg");
+    }
+}
+
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    version.split_whitespace().nth(1)?.split('.').nth(1)?.parse().ok()
 }
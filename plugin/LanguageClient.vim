@@ -70,6 +70,10 @@ function! LanguageClient_registerHandlers(...)
     return call('LanguageClient#registerHandlers', a:000)
 endfunction
 
+function! LanguageClient_registerUriHandlers(...)
+    return call('LanguageClient#registerUriHandlers', a:000)
+endfunction
+
 function! LanguageClient_omniComplete(...)
     return call('LanguageClient#omniComplete', a:000)
 endfunction
@@ -86,6 +90,10 @@ function! LanguageClient_serverStatusMessage(...)
     return call('LanguageClient#serverStatusMessage', a:000)
 endfunction
 
+function! LanguageClient_serverInstances(...)
+    return call('LanguageClient#serverInstances', a:000)
+endfunction
+
 function! LanguageClient_statusLine(...)
     return call('LanguageClient#statusLine', a:000)
 endfunction
@@ -94,6 +102,22 @@ function! LanguageClient_clearDocumentHighlight(...)
     return call('LanguageClient#clearDocumentHighlight', a:000)
 endfunction
 
+function! LanguageClient_workspaceOutline(...)
+    return call('LanguageClient#workspaceOutline', a:000)
+endfunction
+
+function! LanguageClient_textDocument_inlineCompletion(...)
+    return call('LanguageClient#textDocument_inlineCompletion', a:000)
+endfunction
+
+function! LanguageClient_acceptInlineCompletion(...)
+    return call('LanguageClient#acceptInlineCompletion', a:000)
+endfunction
+
+function! LanguageClient_dismissInlineCompletion(...)
+    return call('LanguageClient#dismissInlineCompletion', a:000)
+endfunction
+
 function! LanguageClient_cquery_base(...)
     return call('LanguageClient#cquery_base', a:000)
 endfunction
@@ -102,27 +126,124 @@ function! LanguageClient_cquery_vars(...)
     return call('LanguageClient#cquery_vars', a:000)
 endfunction
 
+function! LanguageClient_memoryUsage(...)
+    return call('LanguageClient#memoryUsage', a:000)
+endfunction
+
+function! LanguageClient_formatChangedRanges(...)
+    return call('LanguageClient#formatChangedRanges', a:000)
+endfunction
+
+function! LanguageClient_metrics(...)
+    return call('LanguageClient#metrics', a:000)
+endfunction
+
+function! LanguageClient_repeatLastCodeAction(...)
+    return call('LanguageClient#repeatLastCodeAction', a:000)
+endfunction
+
+function! LanguageClient_filterReferences(...)
+    return call('LanguageClient#filterReferences', a:000)
+endfunction
+
+function! LanguageClient_previewFormatting(...)
+    return call('LanguageClient#previewFormatting', a:000)
+endfunction
+
+function! LanguageClient_tasks(...)
+    return call('LanguageClient#tasks', a:000)
+endfunction
+
+function! LanguageClient_cancelTask(...)
+    return call('LanguageClient#cancelTask', a:000)
+endfunction
+
+function! LanguageClient_cancelProgress(...)
+    return call('LanguageClient#cancelProgress', a:000)
+endfunction
+
+function! LanguageClient_compareCapabilities(...)
+    return call('LanguageClient#compareCapabilities', a:000)
+endfunction
+
+function! LanguageClient_tags(...)
+    return call('LanguageClient#tags', a:000)
+endfunction
+
+function! LanguageClient_hoverAt(...)
+    return call('LanguageClient#hoverAt', a:000)
+endfunction
+
+function! LanguageClient_definitionAt(...)
+    return call('LanguageClient#definitionAt', a:000)
+endfunction
+
+function! LanguageClient_typeDefinitionAt(...)
+    return call('LanguageClient#typeDefinitionAt', a:000)
+endfunction
+
+function! LanguageClient_implementationAt(...)
+    return call('LanguageClient#implementationAt', a:000)
+endfunction
+
+function! LanguageClient_referencesAt(...)
+    return call('LanguageClient#referencesAt', a:000)
+endfunction
+
+function! LanguageClient_setup(...)
+    return call('LanguageClient#setup', a:000)
+endfunction
+
 command! -nargs=* LanguageClientStart :call LanguageClient#startServer(<f-args>)
 command! LanguageClientStop :call LanguageClient#exit()
-
-augroup languageClient
-    autocmd!
-    autocmd BufNewFile * call LanguageClient#handleBufNewFile()
-    autocmd BufReadPost * call LanguageClient#handleBufReadPost()
-    autocmd BufWritePost * call LanguageClient#handleBufWritePost()
-    autocmd BufDelete * call LanguageClient#handleBufDelete()
-    autocmd TextChanged * call LanguageClient#handleTextChanged()
-    autocmd TextChangedI * call LanguageClient#handleTextChanged()
-    if exists('##TextChangedP')
-        autocmd TextChangedP * call LanguageClient#handleTextChanged()
+command! LanguageClientSetup :call LanguageClient#setup()
+
+" Server commands written by LanguageClient#setup(). Those set in vimrc take
+" precedence.
+let s:serverCommandsFile = expand(get(g:, 'LanguageClient_serverCommandsFile',
+            \ '~/.config/LanguageClient/serverCommands.json'))
+if filereadable(s:serverCommandsFile)
+    try
+        let g:LanguageClient_serverCommands = extend(
+                    \ json_decode(join(readfile(s:serverCommandsFile), "\n")),
+                    \ get(g:, 'LanguageClient_serverCommands', {}))
+    catch
+        echohl WarningMsg
+        echomsg 'LanguageClient: Failed to read ' . s:serverCommandsFile . ': ' . v:exception
+        echohl None
+    endtry
+endif
+
+" First run, i.e., nothing configured yet. Point to guided setup.
+if empty(get(g:, 'LanguageClient_serverCommands', {})) && get(g:, 'LanguageClient_setupBanner', 1)
+    augroup languageClientSetup
+        autocmd!
+        autocmd VimEnter * echomsg 'LanguageClient: No language server configured. '
+                    \ . 'Run :LanguageClientSetup to detect installed ones.'
+    augroup END
+endif
+
+" Autocmds, and thus loading autoload/LanguageClient.vim and launching the
+" binary, are set up on first use, i.e., when a file type with server command
+" or save commands is opened.
+let s:enabled = 0
+function! s:HandleFileType() abort
+    if s:enabled
+        return
     endif
-    autocmd CursorMoved * call LanguageClient#handleCursorMoved()
-    autocmd VimLeavePre * call LanguageClient#handleVimLeavePre()
-
-    autocmd CompleteDone * call LanguageClient#handleCompleteDone()
+    if !has_key(get(g:, 'LanguageClient_serverCommands', {}), &filetype)
+                \ && !has_key(get(g:, 'LanguageClient_saveCommands', {}), &filetype)
+        return
+    endif
+    let s:enabled = 1
 
-    if get(g:, 'LanguageClient_signatureHelpOnCompleteDone', 0)
-        autocmd CompleteDone *
-                    \ call LanguageClient#textDocument_signatureHelp({}, 's:HandleOutputNothing')
+    if LanguageClient#enable()
+        " Events of current buffer are gone already.
+        call LanguageClient#handleBufReadPost()
     endif
+endfunction
+
+augroup languageClient
+    autocmd!
+    autocmd FileType * call s:HandleFileType()
 augroup END
@@ -0,0 +1,370 @@
+//! Stand-alone command line mode, i.e., talking to a language server without vim.
+
+use super::*;
+use crate::lsp::notification::Notification;
+use crate::lsp::request::Request;
+use std::process::{Child, Command};
+
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+    /// Format a file with the language server, write the result and exit.
+    #[structopt(name = "format")]
+    Format {
+        /// Language identifier, inferred from file extension by default.
+        #[structopt(long = "language-id")]
+        languageId: Option<String>,
+        /// Settings file relative to project root, as in g:LanguageClient_settingsPath.
+        #[structopt(long = "settings-path", default_value = ".vim/settings.json")]
+        settingsPath: String,
+        #[structopt(long = "tab-size", default_value = "4")]
+        tab_size: u64,
+        /// Indent with tabs instead of spaces.
+        #[structopt(long = "tabs")]
+        tabs: bool,
+        /// File to format.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Command to start language server, as in g:LanguageClient_serverCommands.
+        #[structopt(raw(last = "true", required = "true"))]
+        command: Vec<String>,
+    },
+}
+
+pub fn run(subcommand: Subcommand) -> Result<()> {
+    match subcommand {
+        Subcommand::Format {
+            languageId,
+            settingsPath,
+            tab_size,
+            tabs,
+            file,
+            command,
+        } => format(
+            &file,
+            languageId,
+            &settingsPath,
+            FormattingOptions {
+                tab_size,
+                insert_spaces: !tabs,
+                properties: HashMap::new(),
+            },
+            &command,
+        ),
+    }
+}
+
+/// Language identifier of file, as in vim filetypes, by its extension. Extensions without known
+/// language identifier are passed as is.
+fn get_language_id(file: &Path) -> Option<String> {
+    let ext = file.extension()?.to_string_lossy().to_lowercase();
+    let languageId = match ext.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "ts" => "typescript",
+        "tsx" => "typescript.tsx",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascript.jsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "cs",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "scala" | "sc" => "scala",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "hs" => "haskell",
+        "ml" | "mli" => "ocaml",
+        "ex" | "exs" => "elixir",
+        "erl" | "hrl" => "erlang",
+        "dart" => "dart",
+        "swift" => "swift",
+        "sh" | "bash" => "sh",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "toml" => "toml",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "md" | "markdown" => "markdown",
+        "vim" => "vim",
+        ext => ext,
+    };
+    Some(languageId.to_owned())
+}
+
+#[test]
+fn test_get_language_id() {
+    assert_eq!(get_language_id(Path::new("src/main.rs")), Some("rust".to_owned()));
+    assert_eq!(get_language_id(Path::new("setup.py")), Some("python".to_owned()));
+    assert_eq!(get_language_id(Path::new("index.ts")), Some("typescript".to_owned()));
+    assert_eq!(get_language_id(Path::new("App.TSX")), Some("typescript.tsx".to_owned()));
+    assert_eq!(get_language_id(Path::new("main.zig")), Some("zig".to_owned()));
+    assert_eq!(get_language_id(Path::new("Makefile")), None);
+}
+
+fn format(
+    file: &Path,
+    languageId: Option<String>,
+    settingsPath: &str,
+    options: FormattingOptions,
+    command: &[String],
+) -> Result<()> {
+    let file = std::fs::canonicalize(file)
+        .with_context(|err| format!("Failed to find file ({}): {}", file.display(), err))?;
+    let languageId = languageId
+        .or_else(|| get_language_id(&file))
+        .ok_or_else(|| err_msg("Failed to infer language id, use --language-id"))?;
+    let root = get_rootPath(&file, &languageId, &None)?.to_path_buf();
+    let settings = read_to_string(root.join(settingsPath))
+        .ok()
+        .and_then(|buffer| serde_json::from_str(&buffer).ok())
+        .map(expand_json_path)
+        .unwrap_or(Value::Null);
+    let text = read_to_string(&file)?;
+
+    let mut process = spawn_server(command)?;
+    let mut server = Server::new(&mut process)?;
+    server.call(
+        lsp::request::Initialize::METHOD,
+        InitializeParams {
+            process_id: Some(u64::from(std::process::id())),
+            root_path: Some(root.to_string_lossy().into_owned()),
+            root_uri: Some(root.to_url()?),
+            initialization_options: Some(
+                get_default_initializationOptions(&languageId)
+                    .combine(&settings["initializationOptions"]),
+            ).filter(|options| !options.is_null()),
+            capabilities: ClientCapabilities::default(),
+            trace: None,
+            workspace_folders: None,
+        },
+    )?;
+    server.notify(lsp::notification::Initialized::METHOD, InitializedParams {})?;
+    if !settings.is_null() {
+        server.notify(
+            lsp::notification::DidChangeConfiguration::METHOD,
+            DidChangeConfigurationParams { settings },
+        )?;
+    }
+    server.notify(
+        lsp::notification::DidOpenTextDocument::METHOD,
+        DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: file.to_url()?,
+                language_id: languageId.clone(),
+                version: 0,
+                text: text.clone(),
+            },
+        },
+    )?;
+
+    let result = server.call(
+        lsp::request::Formatting::METHOD,
+        DocumentFormattingParams {
+            text_document: TextDocumentIdentifier {
+                uri: file.to_url()?,
+            },
+            options,
+        },
+    )?;
+    let edits: Option<Vec<TextEdit>> = serde_json::from_value(result)?;
+    let edits = edits.unwrap_or_default();
+
+    if !edits.is_empty() {
+        let line_ending = detect_line_ending(&text);
+        let lines = split_lines(&text, line_ending);
+        let mut formatted = apply_TextEdits(&lines, &edits)?.join(line_ending);
+        if text.ends_with('\n') {
+            formatted.push_str(line_ending);
+        }
+        std::fs::write(&file, formatted)?;
+    }
+
+    server.shutdown()?;
+    process.wait()?;
+    Ok(())
+}
+
+/// Start language server, do initialize handshake in root and shut it down, to check the
+/// command works. Returns name of server, if it tells. Server not responding within timeout is
+/// killed.
+pub fn verify_server(command: &[String], root: &Path, timeout: Duration) -> Result<String> {
+    let mut process = spawn_server(command)?;
+    let server = Server::new(&mut process)?;
+    let root = root.to_path_buf();
+    let fallback_name = command[0].clone();
+
+    let (tx, rx) = channel();
+    let handle = thread::Builder::new()
+        .name("verify-server".into())
+        .spawn(move || {
+            let _ = tx.send(server.handshake(&root, fallback_name));
+        })?;
+    let result = rx.recv_timeout(timeout);
+    // Exited by now, unless it hangs or failed. Killing it also unblocks the thread.
+    let _ = process.kill();
+    let _ = process.wait();
+    let _ = handle.join();
+
+    match result {
+        Ok(result) => result,
+        Err(_) => bail!("No response to initialize request"),
+    }
+}
+
+fn spawn_server(command: &[String]) -> Result<Child> {
+    let process = Command::new(command.get(0).ok_or_else(|| err_msg("Empty command!"))?)
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|err| format!("Failed to start language server ({:?}): {}", command, err))?;
+    Ok(process)
+}
+
+/// Minimal synchronous connection to a language server process.
+struct Server {
+    reader: BufReader<ChildStdout>,
+    writer: BufWriter<ChildStdin>,
+    id: u64,
+}
+
+impl Server {
+    fn new(process: &mut Child) -> Result<Server> {
+        let reader = BufReader::new(
+            process
+                .stdout
+                .take()
+                .ok_or_else(|| err_msg("Failed to get subprocess stdout"))?,
+        );
+        let writer = BufWriter::new(
+            process
+                .stdin
+                .take()
+                .ok_or_else(|| err_msg("Failed to get subprocess stdin"))?,
+        );
+
+        Ok(Server {
+            reader,
+            writer,
+            id: 0,
+        })
+    }
+
+    fn write(&mut self, message: &Value) -> Result<()> {
+        let message = serde_json::to_string(message)?;
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Value> {
+        let mut content_length = None;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                bail!("Unable to read from language server");
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                if content_length.is_some() {
+                    break;
+                }
+                continue;
+            }
+            let tokens: Vec<&str> = line.splitn(2, ':').collect();
+            if tokens[0].eq_ignore_ascii_case("Content-Length") {
+                let len = tokens
+                    .get(1)
+                    .ok_or_else(|| format_err!("Failed to get length! tokens: {:?}", tokens))?
+                    .trim();
+                content_length = Some(usize::from_str(len)?);
+            }
+        }
+
+        let mut buf = vec![0; content_length.unwrap_or_default()];
+        self.reader.read_exact(buf.as_mut_slice())?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn call<P: Serialize>(&mut self, method: &str, params: P) -> Result<Value> {
+        self.id += 1;
+        let id = self.id;
+        self.write(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+
+        loop {
+            let message = self.read()?;
+            if message.get("method").is_some() {
+                // Request from server, e.g., window/workDoneProgress/create. Acknowledge and
+                // move on. Notifications are dropped.
+                if let Some(server_id) = message.get("id") {
+                    self.write(&json!({
+                        "jsonrpc": "2.0",
+                        "id": server_id,
+                        "result": Value::Null,
+                    }))?;
+                }
+                continue;
+            }
+
+            if message["id"] != json!(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                bail!("{} failed: {}", method, error);
+            }
+            return Ok(message["result"].clone());
+        }
+    }
+
+    fn notify<P: Serialize>(&mut self, method: &str, params: P) -> Result<()> {
+        self.write(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    /// Initialize handshake followed by shutdown. Returns name of server, if it tells.
+    fn handshake(mut self, root: &Path, fallback_name: String) -> Result<String> {
+        let result = self.call(
+            lsp::request::Initialize::METHOD,
+            InitializeParams {
+                process_id: Some(u64::from(std::process::id())),
+                root_path: Some(root.to_string_lossy().into_owned()),
+                root_uri: Some(root.to_url()?),
+                initialization_options: None,
+                capabilities: ClientCapabilities::default(),
+                trace: None,
+                workspace_folders: None,
+            },
+        )?;
+        self.notify(lsp::notification::Initialized::METHOD, InitializedParams {})?;
+        let name = result["serverInfo"]["name"]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .unwrap_or(fallback_name);
+        self.shutdown()?;
+        Ok(name)
+    }
+
+    /// Ask server to exit. Process is to be waited for by caller.
+    fn shutdown(mut self) -> Result<()> {
+        self.call(lsp::request::Shutdown::METHOD, Value::Null)?;
+        self.notify(lsp::notification::Exit::METHOD, Value::Null)?;
+        Ok(())
+    }
+}
@@ -42,7 +42,9 @@ impl State {
             let k = e.to_key();
             result.push(
                 map.remove(&k)
-                    .ok_or_else(|| format_err!("Failed to get value! k: {}", k))?,
+                    .ok_or_else(|| LCError::InvalidParams {
+                        message: format!("missing {}", k),
+                    })?,
             );
         }
 
@@ -86,7 +88,7 @@ impl State {
             is_nvim,
         ): (
             u64,
-            HashMap<String, Vec<String>>,
+            HashMap<String, ServerCommand>,
             Option<String>,
             Option<String>,
             String,
@@ -123,14 +125,102 @@ impl State {
                 .as_ref(),
         )?;
 
-        let (diagnosticsSignsMax, documentHighlightDisplay): (Option<u64>, Value) = self.eval(
+        #[allow(unknown_lints)]
+        #[allow(type_complexity)]
+        let (
+            diagnosticsSignsMax,
+            documentHighlightDisplay,
+            gcInterval,
+            cacheTTL,
+            flashEditsDuration,
+            flashEditsHighlight,
+            warmUp,
+            importVSCodeSettings,
+            outsideRootPolicy,
+            pinnedServers,
+            symbolIndex,
+            saveBeforeWorkspaceEdit,
+            notebookCellMarkers,
+            uriHandlers,
+            suspendOnConflict,
+            metricsAddress,
+        ): (
+            Option<u64>,
+            Value,
+            Option<f64>,
+            HashMap<String, f64>,
+            u64,
+            String,
+            HashMap<String, u64>,
+            u64,
+            String,
+            HashMap<String, String>,
+            u64,
+            u64,
+            HashMap<String, String>,
+            HashMap<String, String>,
+            String,
+            Option<String>,
+        ) = self.eval(
             [
                 "get(g:, 'LanguageClient_diagnosticsSignsMax', v:null)",
                 "get(g:, 'LanguageClient_documentHighlightDisplay', {})",
+                "get(g:, 'LanguageClient_gcInterval', v:null)",
+                "get(g:, 'LanguageClient_cacheTTL', {})",
+                "get(g:, 'LanguageClient_flashEditsDuration', 500)",
+                "get(g:, 'LanguageClient_flashEditsHighlight', 'IncSearch')",
+                "get(g:, 'LanguageClient_warmUp', {})",
+                "get(g:, 'LanguageClient_importVSCodeSettings', 0)",
+                "get(g:, 'LanguageClient_outsideRootPolicy', 'Attach')",
+                "map(copy(get(g:, 'LanguageClient_pinnedServers', {})), 'expand(v:val)')",
+                "get(g:, 'LanguageClient_symbolIndex', 0)",
+                "get(g:, 'LanguageClient_saveBeforeWorkspaceEdit', 0)",
+                "get(g:, 'LanguageClient_notebookCellMarkers', {})",
+                "get(g:, 'LanguageClient_uriHandlers', {})",
+                "get(g:, 'LanguageClient_suspendOnConflict', 'Diagnostics')",
+                "get(g:, 'LanguageClient_metricsAddress', v:null)",
             ]
                 .as_ref(),
         )?;
 
+        #[allow(unknown_lints)]
+        #[allow(type_complexity)]
+        let (
+            serverPicker,
+            changedRangesOnSave,
+            grepFallback,
+            saveCommands,
+            diagnosticsListLive,
+            additionalServerCommands,
+            inlineCompletion,
+        ): (
+            u64,
+            Vec<String>,
+            Value,
+            HashMap<String, Vec<SaveCommand>>,
+            u8,
+            HashMap<String, HashMap<String, ServerCommand>>,
+            u8,
+        ) = self.eval(
+            [
+                "get(g:, 'LanguageClient_serverPicker', 0)",
+                "get(g:, 'LanguageClient_changedRangesOnSave', [])",
+                "get(g:, 'LanguageClient_grepFallback', 0)",
+                "get(g:, 'LanguageClient_saveCommands', {})",
+                "get(g:, 'LanguageClient_diagnosticsListLive', 1)",
+                "get(g:, 'LanguageClient_additionalServerCommands', {})",
+                "get(g:, 'LanguageClient_inlineCompletion', 0)",
+            ]
+                .as_ref(),
+        )?;
+        let grepFallback = match grepFallback {
+            Value::Array(_) => Some(serde_json::from_value(grepFallback)?),
+            Value::Number(ref n) if n.as_u64() == Some(1) => {
+                Some(DefaultGrepCommand.iter().map(|s| s.to_string()).collect())
+            }
+            _ => None,
+        };
+
         // vimscript use 1 for true, 0 for false.
         let autoStart = autoStart == 1;
         let loadSettings = loadSettings == 1;
@@ -140,7 +230,12 @@ impl State {
                 "OFF" => Some(TraceOption::Off),
                 "MESSAGES" => Some(TraceOption::Messages),
                 "VERBOSE" => Some(TraceOption::Verbose),
-                _ => bail!("Invalid option for LanguageClient_trace: {}", t),
+                _ => {
+                    return Err(LCError::InvalidOption {
+                        option: "trace".into(),
+                        value: t,
+                    }.into())
+                }
             }
         } else {
             Some(TraceOption::default())
@@ -171,10 +266,12 @@ impl State {
             "WARNING" => MessageType::Warning,
             "INFO" => MessageType::Info,
             "LOG" => MessageType::Log,
-            _ => bail!(
-                "Invalid option for LanguageClient_windowLogMessageLevel: {}",
-                windowLogMessageLevel
-            ),
+            _ => {
+                return Err(LCError::InvalidOption {
+                    option: "windowLogMessageLevel".into(),
+                    value: windowLogMessageLevel,
+                }.into())
+            }
         };
 
         let hoverPreview = if let Some(s) = hoverPreview {
@@ -185,11 +282,32 @@ impl State {
 
         let completionPreferTextEdit = completionPreferTextEdit == 1;
 
+        let outsideRootPolicy = OutsideRootPolicy::from_str(&outsideRootPolicy)?;
+        let suspendOnConflict = SuspendOnConflict::from_str(&suspendOnConflict)?;
+
+        let gcInterval = gcInterval.map(|t| Duration::from_millis((t * 1000.0) as u64));
+        let mut cacheTTL_parsed = HashMap::new();
+        for (kind, ttl) in cacheTTL {
+            cacheTTL_parsed.insert(
+                CacheKind::from_str(&kind)?,
+                Duration::from_millis((ttl * 1000.0) as u64),
+            );
+        }
+
         let is_nvim = is_nvim == 1;
 
+        match metricsAddress {
+            Some(ref address) if Some(address) != self.metricsAddress.as_ref() => {
+                self.start_metrics_server(address)?;
+            }
+            None => self.stop_metrics_server(),
+            _ => {}
+        }
+
         self.update(|state| {
             state.autoStart = autoStart;
             state.serverCommands.extend(serverCommands);
+            state.additionalServerCommands = additionalServerCommands;
             state.selectionUI = selectionUI;
             state.trace = trace;
             state.diagnosticsEnable = diagnosticsEnable;
@@ -210,6 +328,32 @@ impl State {
             state.wait_output_timeout = wait_output_timeout;
             state.hoverPreview = hoverPreview;
             state.completionPreferTextEdit = completionPreferTextEdit;
+            if let Some(gcInterval) = gcInterval {
+                state.gcInterval = gcInterval;
+            }
+            state.cacheTTL.extend(cacheTTL_parsed);
+            state.flashEditsDuration = flashEditsDuration;
+            state.flashEditsHighlight = flashEditsHighlight;
+            state.warmUp = warmUp.into_iter().map(|(k, v)| (k, v == 1)).collect();
+            state.importVSCodeSettings = importVSCodeSettings == 1;
+            state.outsideRootPolicy = outsideRootPolicy;
+            state.pinnedServers = pinnedServers;
+            state.symbolIndex = symbolIndex == 1;
+            state.saveBeforeWorkspaceEdit = saveBeforeWorkspaceEdit == 1;
+            state.notebookCellMarkers.extend(notebookCellMarkers);
+            state.uriHandlers.extend(
+                uriHandlers
+                    .into_iter()
+                    .map(|(scheme, function)| (scheme, UriHandler::Function(function))),
+            );
+            state.suspendOnConflict = suspendOnConflict;
+            state.metricsAddress = metricsAddress;
+            state.serverPicker = serverPicker == 1;
+            state.changedRangesOnSave = changedRangesOnSave;
+            state.grepFallback = grepFallback;
+            state.saveCommands = saveCommands;
+            state.diagnosticsListLive = diagnosticsListLive == 1;
+            state.inlineCompletion = inlineCompletion == 1;
             state.loggingFile = loggingFile;
             state.loggingLevel = loggingLevel;
             state.serverStderr = serverStderr;
@@ -226,14 +370,609 @@ impl State {
         }
 
         let path = Path::new(root).join(self.settingsPath.clone());
+        if !path.exists() {
+            return Ok(Value::Null);
+        }
         let buffer = read_to_string(&path).with_context(|err| {
             format!("Failed to read file ({}): {}", path.to_string_lossy(), err)
         })?;
-        let value = serde_json::from_str(&buffer)?;
+        let value = serde_json::from_str(&buffer).with_context(|err| {
+            format!("Failed to parse file ({}): {}", path.to_string_lossy(), err)
+        })?;
         let value = expand_json_path(value);
         Ok(value)
     }
 
+    /// Settings for language server from VSCode settings file of the project.
+    fn get_vscode_settings(&self, languageId: &str, root: &str) -> Result<Value> {
+        if !self.importVSCodeSettings {
+            return Ok(Value::Null);
+        }
+
+        let path = Path::new(root).join(".vscode").join("settings.json");
+        if !path.exists() {
+            return Ok(Value::Null);
+        }
+        let buffer = read_to_string(&path).with_context(|err| {
+            format!("Failed to read file ({}): {}", path.to_string_lossy(), err)
+        })?;
+        let value = serde_json::from_str(&strip_json_comments(&buffer))?;
+        let value = import_vscode_settings(value, &get_vscode_settings_sections(languageId));
+        Ok(value)
+    }
+
+    /// Look up a cached response. Entries older than the configured TTL are ignored.
+    fn get_cached(&self, kind: CacheKind, key: &str) -> Option<Value> {
+        let ttl = self.cacheTTL.get(&kind).cloned().unwrap_or_default();
+        self.response_cache
+            .get(&kind)?
+            .get(key)
+            .filter(|entry| entry.created.elapsed() < ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn set_cached(&mut self, kind: CacheKind, key: String, value: &Value) {
+        if self.cacheTTL.get(&kind).cloned().unwrap_or_default() == Duration::default() {
+            return;
+        }
+
+        self.response_cache
+            .entry(kind)
+            .or_insert_with(HashMap::new)
+            .insert(key, CacheEntry::new(value.clone()));
+    }
+
+    /// Cache key for responses that only stay valid for current version of a document.
+    fn document_cache_key(&self, filename: &str, rest: &str) -> String {
+        let version = self
+            .text_documents
+            .get(filename)
+            .map(|doc| doc.version)
+            .unwrap_or_default();
+        format!("{}:{}:{}", filename, version, rest)
+    }
+
+    /// Drop cached responses of a document, e.g., after its version bumped.
+    fn invalidate_cached(&mut self, filename: &str) {
+        let prefix = format!("{}:", filename);
+        for entries in self.response_cache.values_mut() {
+            entries.retain(|key, _| !key.starts_with(&prefix));
+        }
+    }
+
+    /// Whether file should be kept away from running language server as it lies outside of
+    /// project root, according to g:LanguageClient_outsideRootPolicy.
+    fn is_outside_root(&mut self, languageId: &str, filename: &str) -> Result<bool> {
+        if self.outside_root_files.contains_key(filename) {
+            return Ok(true);
+        }
+        match self.outsideRootPolicy {
+            OutsideRootPolicy::Attach | OutsideRootPolicy::Start => return Ok(false),
+            OutsideRootPolicy::Ignore | OutsideRootPolicy::ReadOnly => (),
+        }
+        if self.find_server_key(languageId, filename).is_some() {
+            return Ok(false);
+        }
+        // Any running instance of the language, not necessarily the first one started.
+        let prefix = format!("{}@", languageId);
+        let (server_key, root) = match self
+            .roots
+            .iter()
+            .find(|(key, _)| key.as_str() == languageId || key.starts_with(&prefix))
+        {
+            Some((key, root)) => (key.clone(), root.clone()),
+            None => return Ok(false),
+        };
+
+        self.outside_root_files.insert(filename.to_owned(), server_key);
+        if self.outsideRootPolicy == OutsideRootPolicy::ReadOnly {
+            self.notify(None, "setbufvar", json!([filename, "&readonly", 1]))?;
+            self.notify(None, "setbufvar", json!([filename, "&modifiable", 0]))?;
+        }
+        self.echomsg_ellipsis(format!(
+            "LanguageClient: {} is outside of project root ({}), not attached.",
+            filename, root
+        ))?;
+        Ok(true)
+    }
+
+    /// Whether file type has only g:LanguageClient_saveCommands, i.e., linters, but no language
+    /// server. Documents of those are tracked without being synced to any server.
+    fn is_save_command_only(&self, languageId: &str) -> bool {
+        !self.serverCommands.contains_key(languageId) && self.saveCommands.contains_key(languageId)
+    }
+
+    /// Key of running language server instance whose project root contains the file.
+    ///
+    /// First instance of a language is keyed by languageId, further instances of the same language
+    /// but different project roots by `languageId@rootPath`.
+    fn find_server_key(&self, languageId: &str, filename: &str) -> Option<String> {
+        let prefix = format!("{}@", languageId);
+        self.roots
+            .iter()
+            .filter(|(key, _)| key.as_str() == languageId || key.starts_with(&prefix))
+            .filter(|(_, root)| Path::new(filename).starts_with(root))
+            .max_by_key(|(_, root)| root.len())
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Key of language server instance to route requests of a file to. Defaults to first
+    /// instance of the language.
+    pub fn get_server_key(&self, languageId: &str, filename: &str) -> String {
+        self.find_server_key(languageId, filename)
+            .unwrap_or_else(|| languageId.to_owned())
+    }
+
+    /// Running language server instances covering a file, most specific first. Includes
+    /// additional servers of the language, see g:LanguageClient_additionalServerCommands.
+    fn get_server_candidates(&self, languageId: &str, filename: &str) -> Vec<String> {
+        let languageId = get_server_languageId(languageId);
+        let mut candidates: Vec<(&String, &String)> = self
+            .roots
+            .iter()
+            .filter(|(key, _)| get_server_languageId(key) == languageId)
+            .filter(|(key, _)| self.writers.contains_key(key.as_str()))
+            .filter(|(_, root)| Path::new(filename).starts_with(root))
+            .collect();
+        candidates.sort_by_key(|(_, root)| ::std::cmp::Reverse(root.len()));
+        candidates.into_iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    /// Keys of additional servers started along with the server instance of key.
+    fn get_additional_server_keys(&self, key: &str) -> Vec<String> {
+        let languageId = get_server_languageId(key);
+        let root = self.roots.get(key);
+        self.roots
+            .iter()
+            .filter(|(k, r)| {
+                get_server_languageId(k) == languageId
+                    && get_server_name(k).is_some()
+                    && Some(*r) == root
+            }).map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    /// Server preferred for method among candidates: the pinned one, the one chosen for the
+    /// project, or else the first providing corresponding capability.
+    fn get_preferred_server_key(
+        &self,
+        project_root: &str,
+        candidates: &[String],
+        method: &str,
+    ) -> Option<String> {
+        let preferred_root = self.pinnedServers.get(method).or_else(|| {
+            self.server_choices
+                .get(project_root)
+                .and_then(|choices| choices.get(method))
+        });
+        if let Some(preferred) = preferred_root {
+            let preferred = candidates.iter().find(|key| match get_server_name(key) {
+                Some(name) => name == preferred,
+                None => self.roots.get(key.as_str()) == Some(preferred),
+            });
+            if preferred.is_some() {
+                return preferred.cloned();
+            }
+        }
+
+        let capability = get_method_capability(method)?;
+        candidates
+            .iter()
+            .find(|key| self.has_capability(key, capability))
+            .cloned()
+    }
+
+    /// File remembering servers chosen for a project, next to g:LanguageClient_settingsPath, or
+    /// in `.vim` if that's not relative to project root.
+    fn get_server_choices_path(&self, project_root: &str) -> PathBuf {
+        let settings_dir = Path::new(&self.settingsPath)
+            .parent()
+            .filter(|dir| dir.is_relative())
+            .unwrap_or_else(|| Path::new(".vim"));
+        Path::new(project_root)
+            .join(settings_dir)
+            .join("servers.json")
+    }
+
+    /// Ask which server to use for method, if more than one is capable and none is chosen yet for
+    /// the project. Choice is remembered, see g:LanguageClient_serverPicker.
+    fn pick_server(
+        &mut self,
+        project_root: &str,
+        candidates: &[String],
+        method: &str,
+    ) -> Result<()> {
+        if !self.server_choices.contains_key(project_root) {
+            let path = self.get_server_choices_path(project_root);
+            let choices = read_to_string(&path)
+                .ok()
+                .and_then(|buffer| serde_json::from_str(&buffer).ok())
+                .unwrap_or_default();
+            self.server_choices.insert(project_root.to_owned(), choices);
+        }
+        let chosen = self
+            .server_choices
+            .get(project_root)
+            .map_or(false, |choices| choices.contains_key(method));
+        if chosen || self.pinnedServers.contains_key(method) {
+            return Ok(());
+        }
+
+        let capability = match get_method_capability(method) {
+            Some(capability) => capability,
+            None => return Ok(()),
+        };
+        let capable: Vec<&String> = candidates
+            .iter()
+            .filter(|key| self.has_capability(key, capability))
+            .collect();
+        if capable.len() < 2 {
+            return Ok(());
+        }
+
+        let options: Vec<String> = capable
+            .iter()
+            .map(|key| {
+                let root = self.roots.get(key.as_str()).cloned().unwrap_or_default();
+                format!("{} ({})", key, root)
+            }).collect();
+        let index: i64 = self.call(
+            None,
+            "s:inputList",
+            json!([format!("Language server for {}:", method), options]),
+        )?;
+        let choice = match capable.get(index as usize) {
+            Some(key) if index >= 0 => match get_server_name(key) {
+                Some(name) => name.to_owned(),
+                None => self.roots.get(key.as_str()).cloned().unwrap_or_default(),
+            },
+            _ => return Ok(()),
+        };
+
+        let choices = self
+            .server_choices
+            .entry(project_root.to_owned())
+            .or_insert_with(HashMap::new);
+        choices.insert(method.to_owned(), choice);
+        let buffer = serde_json::to_string_pretty(choices)?;
+        let path = self.get_server_choices_path(project_root);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, buffer)
+            .with_context(|err| format!("Failed to write file ({}): {}", path.display(), err))?;
+        Ok(())
+    }
+
+    /// Key of language server instance to route method of a file to, see
+    /// g:LanguageClient_pinnedServers. Document is synced to the server if it's not the one
+    /// receiving document notifications.
+    pub fn route_server_key(
+        &mut self,
+        languageId: &str,
+        filename: &str,
+        method: &str,
+    ) -> Result<String> {
+        let default = self.get_server_key(languageId, filename);
+        let candidates = self.get_server_candidates(languageId, filename);
+        let project_root = self.roots.get(&default).cloned().unwrap_or_default();
+        if self.serverPicker {
+            self.pick_server(&project_root, &candidates, method)?;
+        }
+        let key = self
+            .get_preferred_server_key(&project_root, &candidates, method)
+            .unwrap_or_else(|| default.clone());
+        if key != default {
+            info!("Routing {} of {} to {}", method, filename, key);
+            self.sync_document(&key, filename)?;
+        }
+        Ok(key)
+    }
+
+    /// Bring document at language server up to date, by didOpen or full didChange.
+    fn sync_document(&mut self, key: &str, filename: &str) -> Result<()> {
+        let document = match self.text_documents.get(filename) {
+            Some(document) => document.clone(),
+            None => return Ok(()),
+        };
+        let synced_version = self
+            .synced_documents
+            .get(key)
+            .and_then(|documents| documents.get(filename))
+            .cloned();
+
+        match synced_version {
+            Some(version) if version == document.version => return Ok(()),
+            Some(_) => self.notify(
+                Some(key),
+                lsp::notification::DidChangeTextDocument::METHOD,
+                DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                        version: Some(document.version),
+                    },
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: document.text.clone(),
+                    }],
+                },
+            )?,
+            None => self.notify(
+                Some(key),
+                lsp::notification::DidOpenTextDocument::METHOD,
+                DidOpenTextDocumentParams {
+                    text_document: document.clone(),
+                },
+            )?,
+        }
+
+        self.synced_documents
+            .entry(key.to_owned())
+            .or_insert_with(HashMap::new)
+            .insert(filename.to_owned(), document.version);
+        Ok(())
+    }
+
+    /// Mark symbols of a document to be indexed again, see g:LanguageClient_symbolIndex. Index is
+    /// brought up to date when queried, so that didOpen and didSave don't wait for the server.
+    fn update_symbol_index(&mut self, filename: &str) {
+        if self.symbolIndex {
+            self.symbol_index_stale.insert(filename.to_owned());
+        }
+    }
+
+    /// Index symbols of documents marked stale. Symbols are requested from language servers all
+    /// at once.
+    fn refresh_symbol_index(&mut self) -> Result<()> {
+        let stale: Vec<String> = self.symbol_index_stale.drain().collect();
+        let mut pending = vec![];
+        for filename in stale {
+            let languageId = match self.text_documents.get(&filename) {
+                Some(document) => document.language_id.clone(),
+                None => continue,
+            };
+            let server_key = self.get_server_key(&languageId, &filename);
+            if !self.writers.contains_key(&server_key)
+                || !self.has_capability(&server_key, "documentSymbolProvider")
+                || self.notebook_documents.contains_key(&filename)
+            {
+                continue;
+            }
+            let (id, start) = self.send_call(
+                Some(&server_key),
+                lsp::request::DocumentSymbolRequest::METHOD,
+                DocumentSymbolParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                },
+            )?;
+            pending.push((filename, server_key, id, start));
+        }
+        for (filename, server_key, id, start) in pending {
+            let result = self
+                .wait_call(
+                    Some(&server_key),
+                    lsp::request::DocumentSymbolRequest::METHOD,
+                    id,
+                    start,
+                ).and_then(|result: Value| flatten_document_symbols(&filename.to_url()?, &result));
+            match result {
+                Ok(symbols) => {
+                    self.symbol_index.insert(filename, symbols);
+                }
+                Err(err) => warn!("Failed to index document symbols of {}: {}", filename, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Outline of symbols of all open documents, grouped by file. Symbols are requested from
+    /// language servers all at once, and only for documents changed since last outline.
+    pub fn languageClient_workspaceOutline(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__WorkspaceOutline);
+        self.textDocument_didChange(params)?;
+        let (handle, refresh): (bool, bool) =
+            self.gather_args(&[("handle", "v:true"), ("refresh", "v:false")], params)?;
+
+        let documents: Vec<_> = self
+            .text_documents
+            .iter()
+            .map(|(filename, document)| {
+                (
+                    filename.clone(),
+                    document.language_id.clone(),
+                    document.version,
+                )
+            }).collect();
+        let mut pending = vec![];
+        for (filename, languageId, version) in documents {
+            if self.outline.get(&filename).map(|outline| outline.0) == Some(version) {
+                continue;
+            }
+            let server_key = self.get_server_key(&languageId, &filename);
+            if !self.writers.contains_key(&server_key)
+                || !self.has_capability(&server_key, "documentSymbolProvider")
+                || self.notebook_documents.contains_key(&filename)
+            {
+                continue;
+            }
+            let uri = filename.to_url()?;
+            let (id, start) = self.send_call(
+                Some(&server_key),
+                lsp::request::DocumentSymbolRequest::METHOD,
+                DocumentSymbolParams {
+                    text_document: TextDocumentIdentifier { uri },
+                },
+            )?;
+            pending.push((filename, version, server_key, id, start));
+        }
+        for (filename, version, server_key, id, start) in pending {
+            let result: Result<Value> = self.wait_call(
+                Some(&server_key),
+                lsp::request::DocumentSymbolRequest::METHOD,
+                id,
+                start,
+            );
+            match result.and_then(|result| get_outline_items(&result)) {
+                Ok(items) => {
+                    self.outline.insert(filename, (version, items));
+                }
+                Err(err) => warn!("Failed to get document symbols of {}: {}", filename, err),
+            }
+        }
+        let text_documents = &self.text_documents;
+        self.outline
+            .retain(|filename, _| text_documents.contains_key(filename));
+
+        if !handle {
+            return Ok(serde_json::to_value(&self.outline)?);
+        }
+
+        let cwd: String = self.eval("getcwd()")?;
+        let mut filenames: Vec<_> = self.outline.keys().cloned().collect();
+        filenames.sort();
+        let mut lines = vec![];
+        let mut locations = vec![];
+        for filename in filenames {
+            let items = &self.outline[&filename].1;
+            if items.is_empty() {
+                continue;
+            }
+            let path = PathBuf::from(&filename);
+            let relpath = diff_paths(&path, Path::new(&cwd)).unwrap_or(path);
+            lines.push(relpath.to_string_lossy().into_owned());
+            locations.push(json!([filename, 1, 1]));
+            for item in items {
+                lines.push(format!(
+                    "{}{} [{:?}]",
+                    "  ".repeat(item.depth + 1),
+                    item.name,
+                    item.kind
+                ));
+                locations.push(json!([
+                    filename,
+                    item.position.line + 1,
+                    item.position.character + 1
+                ]));
+            }
+        }
+        self.notify(
+            None,
+            "s:ShowWorkspaceOutline",
+            json!([lines, locations, refresh]),
+        )?;
+
+        info!("End {}", REQUEST__WorkspaceOutline);
+        Ok(Value::Null)
+    }
+
+    /// Merge symbols indexed client side under project root matching query into workspace
+    /// symbols from language server.
+    fn merge_symbol_index(&self, languageId: &str, query: &str, result: Value) -> Result<Value> {
+        let root = self.roots.get(languageId).cloned().unwrap_or_default();
+        let symbols: Option<Vec<SymbolInformation>> = result.to_lsp()?;
+        let mut symbols = symbols.unwrap_or_default();
+        for (filename, indexed) in &self.symbol_index {
+            if !filename.starts_with(&root) {
+                continue;
+            }
+            for symbol in indexed {
+                let is_duplicate = symbols.iter().any(|s| {
+                    s.name == symbol.name
+                        && s.location.uri == symbol.location.uri
+                        && s.location.range.start == symbol.location.range.start
+                });
+                if is_fuzzy_match(&symbol.name, query) && !is_duplicate {
+                    // SymbolInformation isn't Clone.
+                    symbols.push(SymbolInformation {
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        deprecated: symbol.deprecated,
+                        location: symbol.location.clone(),
+                        container_name: symbol.container_name.clone(),
+                    });
+                }
+            }
+        }
+        Ok(serde_json::to_value(symbols)?)
+    }
+
+    /// Publish list of running language server instances to vim.
+    fn update_server_instances(&mut self) -> Result<()> {
+        let instances: Vec<_> = self
+            .roots
+            .iter()
+            .filter(|(key, _)| self.writers.contains_key(key.as_str()))
+            .map(|(key, root)| {
+                json!({
+                    "languageId": get_server_languageId(key),
+                    "rootPath": root,
+                    "pid": self.child_ids.get(key).cloned().unwrap_or_default(),
+                })
+            }).collect();
+        self.command(&format!(
+            "let {}={}",
+            VIM__ServerInstances,
+            serde_json::to_string(&instances)?
+        ))
+    }
+
+    /// Tell user about capabilities added, removed or changed since language server was last
+    /// started, e.g., after upgrading language server.
+    fn report_capabilities_changes(
+        &mut self,
+        languageId: &str,
+        previous: &Value,
+        current: &Value,
+    ) -> Result<()> {
+        let (added, removed, changed) = diff_capabilities(previous, current);
+        if added.is_empty() && removed.is_empty() && changed.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "Capabilities changed. added: {:?}, removed: {:?}, changed: {:?}",
+            added, removed, changed
+        );
+        let mut msg = format!("LanguageClient: {} capabilities changed.", languageId);
+        if !added.is_empty() {
+            msg += &format!(" Added: {}.", added.join(", "));
+        }
+        if !removed.is_empty() {
+            msg += &format!(" Removed: {}.", removed.join(", "));
+        }
+        if !changed.is_empty() {
+            msg += &format!(" Changed: {}.", changed.join(", "));
+        }
+        self.echomsg(&msg)?;
+        self.notify(None, "s:ExecuteAutocmd", "LanguageClientCapabilitiesChanged")?;
+        Ok(())
+    }
+
+    /// Whether language server advertised a capability in its initialize result.
+    fn has_capability(&self, languageId: &str, capability: &str) -> bool {
+        match self
+            .capabilities
+            .get(languageId)
+            .map(|result| &result["capabilities"][capability])
+        {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            _ => true,
+        }
+    }
+
+    /// Write all modified buffers, so that language server doesn't compute edits against stale
+    /// files on disk. See g:LanguageClient_saveBeforeWorkspaceEdit.
+    fn save_all(&mut self) -> Result<()> {
+        if !self.saveBeforeWorkspaceEdit {
+            return Ok(());
+        }
+        info!("Writing all modified buffers");
+        self.command("silent! wall")
+    }
+
     fn define_signs(&mut self) -> Result<()> {
         info!("Defining signs");
 
@@ -246,6 +985,7 @@ impl State {
         }
 
         self.command(cmds)?;
+        self.signs_defined = true;
         Ok(())
     }
 
@@ -257,17 +997,22 @@ impl State {
         let (filename, line, character): (String, u64, u64) =
             self.gather_args(&[VimVar::Filename, VimVar::Line, VimVar::Character], params)?;
 
+        let mut edited = vec![];
         if let Some(ref changes) = edit.document_changes {
             match changes {
                 DocumentChanges::Edits(ref changes) => {
                     for e in changes {
-                        self.apply_TextEdits(&e.text_document.uri.filepath()?, &e.edits)?;
+                        let path = e.text_document.uri.filepath()?;
+                        self.apply_TextEdits(&path, &e.edits)?;
+                        edited.push((path, get_TextEdits_ranges(&e.edits)));
                     }
                 }
                 DocumentChanges::Operations(ref ops) => {
                     for op in ops {
                         if let DocumentChangeOperation::Edit(ref e) = op {
-                            self.apply_TextEdits(&e.text_document.uri.filepath()?, &e.edits)?;
+                            let path = e.text_document.uri.filepath()?;
+                            self.apply_TextEdits(&path, &e.edits)?;
+                            edited.push((path, get_TextEdits_ranges(&e.edits)));
                         }
                         // TODO: handle ResourceOp.
                     }
@@ -276,15 +1021,71 @@ impl State {
         }
         if let Some(ref changes) = edit.changes {
             for (uri, edits) in changes {
-                self.apply_TextEdits(&uri.filepath()?, edits)?;
+                let path = uri.filepath()?;
+                self.apply_TextEdits(&path, edits)?;
+                edited.push((path, get_TextEdits_ranges(edits)));
             }
         }
-        self.edit(&None, &filename)?;
-        self.cursor(line + 1, character + 1)?;
+        if self.edit(&None, &filename)? {
+            self.cursor(line + 1, character + 1)?;
+            self.flash_edits(&filename, &edited)?;
+        }
         debug!("End apply WorkspaceEdit");
         Ok(())
     }
 
+    /// Briefly highlight edited ranges visible in current window, and report the rest.
+    fn flash_edits(&mut self, filename: &str, edited: &[(PathBuf, Vec<Range>)]) -> Result<()> {
+        let (visible_line_start, visible_line_end, cwd): (u64, u64, String) = self.eval(
+            [
+                "LSP#visible_line_start()",
+                "LSP#visible_line_end()",
+                "getcwd()",
+            ]
+                .as_ref(),
+        )?;
+
+        let current = filename.canonicalize();
+        let mut positions = vec![];
+        let mut outside = vec![];
+        for (path, ranges) in edited {
+            let is_current = path.canonicalize() == current;
+            for range in ranges {
+                if is_current
+                    && range.start.line >= visible_line_start
+                    && range.start.line <= visible_line_end
+                {
+                    positions.extend(get_matchaddpos_positions(range));
+                } else {
+                    let relpath = diff_paths(path, Path::new(&cwd)).unwrap_or_else(|| path.clone());
+                    outside.push(format!(
+                        "{}:{}",
+                        relpath.to_string_lossy(),
+                        range.start.line + 1
+                    ));
+                }
+            }
+        }
+
+        if self.flashEditsDuration > 0 && !positions.is_empty() {
+            let group = self.flashEditsHighlight.clone();
+            self.notify(
+                None,
+                "s:FlashRanges",
+                json!([positions, group, self.flashEditsDuration]),
+            )?;
+        }
+        if !outside.is_empty() {
+            self.echomsg_ellipsis(format!(
+                "[LC] {} edit(s) outside of view: {}",
+                outside.len(),
+                outside.join(", ")
+            ))?;
+        }
+
+        Ok(())
+    }
+
     pub fn textDocument_documentHighlight(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::DocumentHighlightRequest::METHOD);
@@ -304,21 +1105,30 @@ impl State {
             ],
             params,
         )?;
+        let languageId = self.route_server_key(
+            &languageId,
+            &filename,
+            lsp::request::DocumentHighlightRequest::METHOD,
+        )?;
 
+        let generation = self.document_generation(&filename);
         let result = self.call(
             Some(&languageId),
             lsp::request::DocumentHighlightRequest::METHOD,
-            TextDocumentPositionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
-            },
+            self.text_document_position(&filename, line, character)?,
         )?;
+        let result = shift_ranges(result, self.notebook_cell_offset(&filename, line));
 
         if !handle {
             return Ok(result);
         }
+        // Highlights are placed into current buffer, which might have been switched or unloaded
+        // since the request was sent.
+        let current_filename: String = self.eval(VimVar::Filename)?;
+        if current_filename != filename || !self.is_document_current(&filename, generation) {
+            info!("Buffer changed since request. Dropping highlights. filename: {}", filename);
+            return Ok(Value::Null);
+        }
 
         let document_highlight: Option<Vec<DocumentHighlight>> =
             serde_json::from_value(result.clone())?;
@@ -337,7 +1147,10 @@ impl State {
                                     .unwrap_or(DocumentHighlightKind::Text)
                                     .to_int()
                                     .unwrap(),
-                            ).ok_or_else(|| err_msg("Failed to get display"))?
+                            ).ok_or_else(|| LCError::InvalidOption {
+                                option: "documentHighlightDisplay".into(),
+                                value: format!("missing {:?}", kind),
+                            })?
                             .texthl
                             .clone(),
                         text: String::new(),
@@ -406,6 +1219,140 @@ impl State {
         Ok(())
     }
 
+    pub fn textDocument_inlineCompletion(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__InlineCompletion);
+        self.textDocument_didChange(params)?;
+        let (buftype, languageId, filename, line, character, handle): (
+            String,
+            String,
+            String,
+            u64,
+            u64,
+            bool,
+        ) = self.gather_args(
+            &[
+                VimVar::Buftype,
+                VimVar::LanguageId,
+                VimVar::Filename,
+                VimVar::Line,
+                VimVar::Character,
+                VimVar::Handle,
+            ],
+            params,
+        )?;
+        if !buftype.is_empty() || languageId.is_empty() {
+            return Ok(Value::Null);
+        }
+        // 1: invoked explicitly, 2: triggered automatically while typing.
+        let (triggerKind,): (u64,) = self.gather_args(&[("triggerKind", "1")], params)?;
+        let languageId = self.route_server_key(&languageId, &filename, REQUEST__InlineCompletion)?;
+        let generation = self.document_generation(&filename);
+
+        let document_position = self.text_document_position(&filename, line, character)?;
+        let params = serde_json::to_value(document_position)?.combine(&json!({
+            "context": { "triggerKind": triggerKind },
+        }));
+        let result = self.call(Some(&languageId), REQUEST__InlineCompletion, params)?;
+        let result = shift_ranges(result, self.notebook_cell_offset(&filename, line));
+
+        if !handle {
+            return Ok(result);
+        }
+
+        self.languageClient_dismissInlineCompletion(&Value::Null)?;
+        // Suggestion is stale once user typed on or moved away.
+        let (filename_now, line_now, character_now): (String, u64, u64) = self.gather_args(
+            &[VimVar::Filename, VimVar::Line, VimVar::Character],
+            &Value::Null,
+        )?;
+        if !self.is_document_current(&filename, generation)
+            || (filename_now.as_str(), line_now, character_now)
+                != (filename.as_str(), line, character)
+        {
+            return Ok(result);
+        }
+
+        let response: Option<InlineCompletionResponse> = result.clone().to_lsp()?;
+        let item = match response.and_then(|response| response.into_items().into_iter().next()) {
+            Some(item) => item,
+            None => return Ok(result),
+        };
+        let text = self
+            .text_documents
+            .get(&filename)
+            .and_then(|document| document.text.lines().nth(line as usize).map(ToOwned::to_owned))
+            .unwrap_or_default();
+        let position = Position { line, character };
+        let range = item.range.unwrap_or(Range {
+            start: position,
+            end: position,
+        });
+        if let Some((text, delete)) =
+            get_inline_completion_ghost(&text, position, range, &item.insert_text.text())
+        {
+            if !text.is_empty() {
+                self.notify(None, "s:ShowInlineCompletion", json!([line, text]))?;
+                self.inline_completion = Some(InlineCompletion {
+                    languageId,
+                    filename,
+                    position,
+                    text,
+                    delete,
+                    command: item.command,
+                });
+            }
+        }
+
+        info!("End {}", REQUEST__InlineCompletion);
+        Ok(result)
+    }
+
+    /// Accept inline completion shown as ghost text. Returns text to insert at cursor and number
+    /// of characters after cursor to delete before that, for vim to type in.
+    pub fn languageClient_acceptInlineCompletion(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__AcceptInlineCompletion);
+        let (filename, line, character): (String, u64, u64) = self.gather_args(
+            &[VimVar::Filename, VimVar::Line, VimVar::Character],
+            params,
+        )?;
+        let completion = match self.inline_completion.clone() {
+            Some(completion)
+                if completion.filename == filename
+                    && completion.position == (Position { line, character }) =>
+            {
+                completion
+            }
+            _ => return Ok(Value::Null),
+        };
+        self.languageClient_dismissInlineCompletion(&Value::Null)?;
+
+        if let Some(command) = completion.command {
+            self.call::<_, Value>(
+                Some(&completion.languageId),
+                lsp::request::ExecuteCommand::METHOD,
+                ExecuteCommandParams {
+                    command: command.command,
+                    arguments: command.arguments.unwrap_or_default(),
+                },
+            )?;
+        }
+
+        info!("End {}", REQUEST__AcceptInlineCompletion);
+        Ok(json!({
+            "text": completion.text,
+            "delete": completion.delete,
+        }))
+    }
+
+    pub fn languageClient_dismissInlineCompletion(&mut self, _: &Value) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__DismissInlineCompletion);
+        if self.inline_completion.take().is_some() {
+            self.notify(None, "s:ClearInlineCompletion", json!([]))?;
+        }
+        info!("End {}", NOTIFICATION__DismissInlineCompletion);
+        Ok(())
+    }
+
     fn apply_TextEdits<P: AsRef<Path>>(&mut self, path: P, edits: &[TextEdit]) -> Result<()> {
         debug!("Begin apply TextEdits: {:?}", edits);
         if edits.is_empty() {
@@ -423,7 +1370,11 @@ impl State {
         edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
         edits.reverse();
 
-        self.edit(&None, &path)?;
+        if !self.edit(&None, &path)? {
+            return Err(LCError::EditCancelled {
+                filename: path.as_ref().to_string_lossy().into_owned(),
+            }.into());
+        }
 
         let mut lines: Vec<String> = self.call(None, "getline", json!([1, '$']))?;
         let lines_len_prev = lines.len();
@@ -441,35 +1392,57 @@ impl State {
             self.command(format!("{},{}d", lines.len() + 1, lines_len_prev))?;
         }
         if self.call::<_, i64>(None, "setline", json!([1, lines]))? != 0 {
-            bail!("Failed to set buffer content!");
+            return Err(LCError::VimCallFailed {
+                function: "setline".into(),
+            }.into());
         }
         debug!("End apply TextEdits");
         Ok(())
     }
 
     fn update_quickfixlist(&mut self) -> Result<()> {
-        let qflist: Vec<_> = self
-            .diagnostics
-            .iter()
-            .flat_map(|(filename, diagnostics)| {
-                diagnostics
-                    .iter()
-                    .map(|dn| QuickfixEntry {
-                        filename: filename.to_owned(),
-                        lnum: dn.range.start.line + 1,
-                        col: Some(dn.range.start.character + 1),
-                        nr: dn.code.clone().map(|ns| ns.to_string()),
-                        text: Some(dn.message.to_owned()),
-                        typ: dn.severity.map(|sev| sev.to_quickfix_entry_type()),
-                    }).collect::<Vec<_>>()
-            }).collect();
+        let to_entries = |filename: &str, diagnostics: &[Diagnostic]| -> Vec<QuickfixEntry> {
+            diagnostics
+                .iter()
+                .map(|dn| QuickfixEntry {
+                    filename: filename.to_owned(),
+                    lnum: dn.range.start.line + 1,
+                    col: Some(dn.range.start.character + 1),
+                    nr: dn.code.clone().map(|ns| ns.to_string()),
+                    text: Some(dn.message.to_owned()),
+                    typ: dn.severity.map(|sev| sev.to_quickfix_entry_type()),
+                }).collect()
+        };
 
         match self.diagnosticsList {
             DiagnosticsList::Quickfix => {
+                let qflist: Vec<_> = self
+                    .diagnostics
+                    .iter()
+                    .flat_map(|(filename, diagnostics)| to_entries(filename, diagnostics))
+                    .collect();
                 self.setqflist(&qflist)?;
             }
             DiagnosticsList::Location => {
-                self.setloclist(&qflist)?;
+                // Each window gets diagnostics of the file it displays, so that a buffer shown
+                // in several windows has all of them updated.
+                let windows: Vec<(u64, String)> = self.eval(
+                    "map(getwininfo(), \
+                     '[v:val.winid, fnamemodify(bufname(v:val.bufnr), \":p\")]')",
+                )?;
+                let mut lists = vec![];
+                for (winid, filename) in windows {
+                    if let Some(diagnostics) = self.diagnostics.get(&filename.canonicalize()) {
+                        lists.push((winid, to_entries(&filename, diagnostics)));
+                    } else if self.text_documents.contains_key(&filename) {
+                        lists.push((winid, vec![]));
+                    }
+                }
+                self.notify(
+                    None,
+                    "s:SetDiagnosticsLocationLists",
+                    json!([lists, self.diagnosticsListLive]),
+                )?;
             }
             DiagnosticsList::Disabled => {}
         }
@@ -481,6 +1454,11 @@ impl State {
         if !self.text_documents.contains_key(filename) {
             return Ok(());
         }
+        let diagnostics: &[Diagnostic] = if self.suspended_documents.contains(filename) {
+            &[]
+        } else {
+            diagnostics
+        };
 
         let lines: Vec<_> = self
             .text_documents
@@ -542,7 +1520,10 @@ impl State {
             let severity = dn.severity.unwrap_or(DiagnosticSeverity::Hint);
             let group = diagnosticsDisplay
                 .get(&severity.to_int()?)
-                .ok_or_else(|| err_msg("Failed to get display"))?
+                .ok_or_else(|| LCError::InvalidOption {
+                    option: "diagnosticsDisplay".into(),
+                    value: format!("missing {:?}", severity),
+                })?
                 .texthl
                 .clone();
             // TODO: handle multi-line range.
@@ -588,7 +1569,10 @@ impl State {
             for (severity, dns) in match_groups {
                 let hl_group = diagnosticsDisplay
                     .get(&severity)
-                    .ok_or_else(|| err_msg("Failed to get display"))?
+                    .ok_or_else(|| LCError::InvalidOption {
+                        option: "diagnosticsDisplay".into(),
+                        value: format!("missing {}", severity),
+                    })?
                     .texthl
                     .clone();
                 let ranges: Vec<Vec<_>> = dns
@@ -696,6 +1680,76 @@ impl State {
         Ok(())
     }
 
+    /// Search project for word under cursor with g:LanguageClient_grepFallback, when language
+    /// server found nothing. Results are labeled as text matches.
+    fn grep_fallback(&mut self, languageId: &str, params: &Value) -> Result<()> {
+        let (word,): (String,) = self.gather_args(&[VimVar::Cword], params)?;
+        let command = match self.grepFallback {
+            Some(ref command) if !word.is_empty() => command.clone(),
+            _ => return Ok(()),
+        };
+        let root = match self.roots.get(languageId) {
+            Some(root) => root.clone(),
+            None => self.eval("getcwd()")?,
+        };
+        let placeholders = hashmap!{
+            "word" => word.clone(),
+            "rootPath" => root.clone(),
+        };
+        let command = expand_command_placeholders(&command, &placeholders, false);
+        info!("Searching text matches: {:?}", command);
+
+        let output = std::process::Command::new(
+            command.get(0).ok_or_else(|| LCError::InvalidCommand {
+                message: "empty grep command".into(),
+            })?,
+        ).args(&command[1..])
+        .current_dir(&root)
+        .output()
+        .with_context(|err| format!("Failed to run grep command ({:?}): {}", command, err))?;
+        let entries: Vec<_> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| parse_vimgrep_line(line, Path::new(&root)))
+            .collect();
+        if entries.is_empty() {
+            self.echowarn("Not found!")?;
+            return Ok(());
+        }
+
+        match self.get(|state| Ok(state.selectionUI.clone()))? {
+            SelectionUI::FZF => {
+                let cwd: String = self.eval("getcwd()")?;
+                let source: Vec<_> = entries
+                    .iter()
+                    .map(|entry| {
+                        let filename = PathBuf::from(&entry.filename);
+                        let relpath = diff_paths(&filename, Path::new(&cwd)).unwrap_or(filename);
+                        format!(
+                            "{}:{}:{}:\t{}",
+                            relpath.to_string_lossy(),
+                            entry.lnum,
+                            entry.col.unwrap_or(1),
+                            entry.text.clone().unwrap_or_default()
+                        )
+                    }).collect();
+                self.call::<_, u8>(
+                    None,
+                    "s:FZF",
+                    json!([source, format!("s:{}", NOTIFICATION__FZFSinkLocation)]),
+                )?;
+            }
+            SelectionUI::Quickfix => {
+                self.setqflist(&entries)?;
+                self.echo("Text matches found. Quickfix list updated.")?;
+            }
+            SelectionUI::LocationList => {
+                self.setloclist(&entries)?;
+                self.echo("Text matches found. Location list updated.")?;
+            }
+        }
+        Ok(())
+    }
+
     fn registerCMSource(&mut self, languageId: &str, result: &Value) -> Result<()> {
         info!("Begin register NCM source");
         let exists_CMRegister: u64 = self.eval("exists('g:cm_matcher')")?;
@@ -778,6 +1832,28 @@ impl State {
         Ok(())
     }
 
+    /// Content of document with uri of a scheme not backed by file on disk, e.g., `jdt://`, as
+    /// materialized by handler registered for the scheme. None if there's no such handler.
+    pub fn read_uri(&mut self, languageId: &str, uri: &str) -> Result<Option<Vec<String>>> {
+        let handler = get_uri_scheme(uri).and_then(|scheme| self.uriHandlers.get(scheme).cloned());
+        let lines: Vec<String> = match handler {
+            Some(UriHandler::Request(method)) => {
+                let content: String = self.call(
+                    Some(languageId),
+                    &method,
+                    json!({
+                        "uri": uri,
+                        "textDocument": { "uri": uri },
+                    }),
+                )?;
+                content.lines().map(std::string::ToString::to_string).collect()
+            }
+            Some(UriHandler::Function(function)) => self.call(None, &function, json!([uri]))?,
+            Some(UriHandler::File) | Some(UriHandler::Vim) | None => return Ok(None),
+        };
+        Ok(Some(lines))
+    }
+
     fn get_line<P: AsRef<Path>>(&mut self, path: P, line: u64) -> Result<String> {
         let value = self.call(
             None,
@@ -787,12 +1863,24 @@ impl State {
         let mut texts: Vec<String> = serde_json::from_value(value)?;
         let mut text = texts.pop().unwrap_or_default();
 
-        if text.is_empty() {
-            let reader = BufReader::new(File::open(path)?);
+        let uri = path.as_ref().to_string_lossy().into_owned();
+        if text.is_empty() && get_uri_scheme(&uri).is_some() {
+            let (languageId, filename): (String, String) =
+                self.gather_args(&[VimVar::LanguageId, VimVar::Filename], &Value::Null)?;
+            let languageId = self.get_server_key(&languageId, &filename);
+            text = self
+                .read_uri(&languageId, &uri)?
+                .and_then(|lines| lines.get(line.to_usize().ok()?).cloned())
+                .unwrap_or_default();
+        } else if text.is_empty() {
+            let reader = BufReader::new(File::open(path.as_ref())?);
             text = reader
                 .lines()
                 .nth(line.to_usize()?)
-                .ok_or_else(|| format_err!("Failed to get line! line: {}", line))??;
+                .ok_or_else(|| LCError::PositionOutOfBounds {
+                    filename: path.as_ref().to_string_lossy().into_owned(),
+                    line,
+                })??;
         }
 
         Ok(text.trim().into())
@@ -810,13 +1898,69 @@ impl State {
                     self.apply_WorkspaceEdit(&edit, &Value::Null)?;
                 }
             }
+        } else if cmd.command == "rls.run" {
+            // Arguments: [{ "binary": .., "args": [..], "env": {..}, "cwd": .. }]
+            let runnable = cmd
+                .arguments
+                .as_ref()
+                .and_then(|args| args.get(0))
+                .ok_or_else(|| LCError::InvalidParams {
+                    message: format!("missing runnable in {:?}", cmd),
+                })?;
+            let binary: String = serde_json::from_value(runnable["binary"].clone())?;
+            let mut command = vec![binary];
+            let args: Option<Vec<String>> = serde_json::from_value(runnable["args"].clone())?;
+            command.extend(args.unwrap_or_default());
+            let env: Option<HashMap<String, String>> =
+                serde_json::from_value(runnable["env"].clone())?;
+            let cwd: Option<String> = serde_json::from_value(runnable["cwd"].clone())?;
+            self.run_task(&cmd.title, command, env.unwrap_or_default(), cwd)?;
+        } else if cmd.command == "rust-analyzer.runSingle" {
+            // Arguments: [{ "label": .., "kind": "cargo", "args": { "workspaceRoot": ..,
+            // "cargoArgs": [..], "executableArgs": [..] } }]
+            let runnable = cmd
+                .arguments
+                .as_ref()
+                .and_then(|args| args.get(0))
+                .ok_or_else(|| LCError::InvalidParams {
+                    message: format!("missing runnable in {:?}", cmd),
+                })?;
+            let args = &runnable["args"];
+            let mut command = vec!["cargo".to_owned()];
+            let cargo_args: Option<Vec<String>> =
+                serde_json::from_value(args["cargoArgs"].clone())?;
+            command.extend(cargo_args.unwrap_or_default());
+            let executable_args: Option<Vec<String>> =
+                serde_json::from_value(args["executableArgs"].clone())?;
+            let executable_args = executable_args.unwrap_or_default();
+            if !executable_args.is_empty() {
+                command.push("--".to_owned());
+                command.extend(executable_args);
+            }
+            let cwd: Option<String> = serde_json::from_value(args["workspaceRoot"].clone())?;
+            self.run_task(&cmd.title, command, HashMap::new(), cwd)?;
         } else {
-            bail!("Not implemented: {}", cmd.command);
+            return Err(LCError::MethodNotFound {
+                method: cmd.command.clone(),
+            }.into());
         }
 
         Ok(true)
     }
 
+    /// Run a long-running command, e.g., build or test, as a job managed by vim. Output is
+    /// parsed into quickfix list when the job finishes.
+    fn run_task(
+        &mut self,
+        title: &str,
+        command: Vec<String>,
+        env: HashMap<String, String>,
+        cwd: Option<String>,
+    ) -> Result<()> {
+        info!("Running task ({}): {:?} {:?}", title, env, command);
+        self.notify(None, "s:RunTask", json!([title, command, cwd, env]))
+    }
+
     fn cleanup(&mut self, languageId: &str) -> Result<()> {
         info!("Begin cleanup");
 
@@ -824,27 +1968,53 @@ impl State {
             .roots
             .get(languageId)
             .cloned()
-            .ok_or_else(|| format_err!("No project root found! languageId: {}", languageId))?;
-
-        let mut filenames = vec![];
-        for f in self.diagnostics.keys() {
-            if f.starts_with(&root) {
-                filenames.push(f.clone());
+            .ok_or_else(|| LCError::ServerNotRunning {
+                languageId: languageId.to_owned(),
+            })?;
+        // Documents and diagnostics of the project belong to its main server.
+        let additional = get_server_name(languageId).is_some();
+
+        if !additional {
+            let mut filenames = vec![];
+            for f in self.diagnostics.keys() {
+                if f.starts_with(&root) {
+                    filenames.push(f.clone());
+                }
             }
-        }
-        for f in filenames {
-            self.process_diagnostics(&f, &[])?;
-        }
-        self.languageClient_handleCursorMoved(&Value::Null)?;
+            for f in filenames {
+                self.process_diagnostics(&f, &[])?;
+            }
+            self.languageClient_handleCursorMoved(&Value::Null)?;
 
-        self.diagnostics.retain(|f, _| !f.starts_with(&root));
-        self.update_quickfixlist()?;
+            self.diagnostics.retain(|f, _| !f.starts_with(&root));
+            self.update_quickfixlist()?;
+        }
 
         self.writers.remove(languageId);
         self.child_ids.remove(languageId);
-        self.last_cursor_line = 0;
-        self.text_documents.retain(|f, _| !f.starts_with(&root));
+        self.registrations.remove(languageId);
+        self.watchers.remove(languageId);
+        self.watcher_rxs.remove(languageId);
+        if let Some(capabilities) = self.capabilities.remove(languageId) {
+            self.previous_capabilities.insert(languageId.to_owned(), capabilities);
+        }
+        if !additional {
+            self.last_cursor_line = 0;
+            self.text_documents.retain(|f, _| !f.starts_with(&root));
+        }
         self.roots.remove(languageId);
+        self.warmup_pending.remove(languageId);
+        self.progress.remove(languageId);
+        self.synced_documents.remove(languageId);
+        self.outside_root_files
+            .retain(|_, server_key| server_key != languageId);
+        self.update_server_instances()?;
+        if additional {
+            info!("End cleanup");
+            return Ok(());
+        }
+
+        self.notify(None, "s:RestoreOmnifunc", json!([get_server_languageId(languageId)]))?;
 
         self.command(vec![
             format!("let {}=0", VIM__ServerStatus),
@@ -875,7 +2045,9 @@ impl State {
                 json!([bufnr, 0, -1, 0, lines]),
             )?;
         } else if self.call::<_, i64>(None, "setbufline", json!([bufname, 1, lines]))? != 0 {
-            bail!("Failed to set preview buffer content!");
+            return Err(LCError::VimCallFailed {
+                function: "setbufline".into(),
+            }.into());
             // TODO: removing existing bottom lines.
         }
 
@@ -886,28 +2058,15 @@ impl State {
 
     fn initialize(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", lsp::request::Initialize::METHOD);
-        let (languageId, filename): (String, String) =
-            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
-        let (rootPath, has_snippet_support): (Option<String>, u64) = self.gather_args(
+        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (root, has_snippet_support): (String, u64) = self.gather_args(
             &[
                 ("rootPath", "v:null"),
                 ("hasSnippetSupport", "s:hasSnippetSupport()"),
             ],
             params,
         )?;
-        let root = if let Some(r) = rootPath {
-            r
-        } else {
-            let rootMarkers = self.get(|state| Ok(state.rootMarkers.clone()))?;
-            let root = get_rootPath(Path::new(&filename), &languageId, &rootMarkers)?
-                .to_string_lossy()
-                .into_owned();
-            self.echomsg_ellipsis(format!("LanguageClient project root: {}", root))?;
-            root
-        };
-        info!("Project root: {}", root);
         let has_snippet_support = has_snippet_support > 0;
-        self.update(|state| Ok(state.roots.insert(languageId.clone(), root.clone())))?;
 
         let initialization_options = self
             .get_workspace_settings(&root)
@@ -917,7 +2076,8 @@ impl State {
                 json!(Value::Null)
             });
         let initialization_options =
-            get_default_initializationOptions(&languageId).combine(&initialization_options);
+            get_default_initializationOptions(get_server_languageId(&languageId))
+                .combine(&initialization_options);
         let initialization_options = if initialization_options.is_null() {
             None
         } else {
@@ -926,38 +2086,58 @@ impl State {
 
         let trace = self.trace.clone();
 
-        let result: Value = self.call(
-            Some(&languageId),
-            lsp::request::Initialize::METHOD,
-            InitializeParams {
-                process_id: Some(u64::from(std::process::id())),
-                root_path: Some(root.clone()),
-                root_uri: Some(root.to_url()?),
-                initialization_options,
-                capabilities: ClientCapabilities {
-                    text_document: Some(TextDocumentClientCapabilities {
-                        completion: Some(CompletionCapability {
-                            completion_item: Some(CompletionItemCapability {
-                                snippet_support: Some(has_snippet_support),
-                                ..CompletionItemCapability::default()
-                            }),
-                            ..CompletionCapability::default()
+        let params = serde_json::to_value(InitializeParams {
+            process_id: Some(u64::from(std::process::id())),
+            root_path: Some(root.clone()),
+            root_uri: Some(root.to_url()?),
+            initialization_options,
+            capabilities: ClientCapabilities {
+                text_document: Some(TextDocumentClientCapabilities {
+                    completion: Some(CompletionCapability {
+                        completion_item: Some(CompletionItemCapability {
+                            snippet_support: Some(has_snippet_support),
+                            ..CompletionItemCapability::default()
                         }),
-                        ..TextDocumentClientCapabilities::default()
+                        ..CompletionCapability::default()
                     }),
-                    workspace: Some(WorkspaceClientCapabilities {
-                        apply_edit: Some(true),
-                        did_change_watched_files: Some(GenericCapability {
-                            dynamic_registration: Some(true),
-                        }),
-                        ..WorkspaceClientCapabilities::default()
+                    ..TextDocumentClientCapabilities::default()
+                }),
+                workspace: Some(WorkspaceClientCapabilities {
+                    apply_edit: Some(true),
+                    did_change_watched_files: Some(GenericCapability {
+                        dynamic_registration: Some(true),
                     }),
-                    ..ClientCapabilities::default()
-                },
-                trace,
-                workspace_folders: None,
+                    ..WorkspaceClientCapabilities::default()
+                }),
+                ..ClientCapabilities::default()
             },
-        )?;
+            trace,
+            workspace_folders: None,
+        })?;
+        // Capabilities not yet covered by languageserver-types.
+        let params = params.combine(&json!({
+            "capabilities": {
+                "textDocument": if self.inlineCompletion {
+                    json!({ "inlineCompletion": { "dynamicRegistration": false } })
+                } else {
+                    json!({})
+                },
+                "workspace": {
+                    "codeLens": { "refreshSupport": true },
+                },
+                "window": {
+                    "workDoneProgress": true,
+                },
+                "notebookDocument": {
+                    "synchronization": {
+                        "dynamicRegistration": false,
+                        "executionSummarySupport": false,
+                    }
+                },
+            }
+        }));
+
+        let result: Value = self.call(Some(&languageId), lsp::request::Initialize::METHOD, params)?;
 
         self.update(|state| {
             state
@@ -968,12 +2148,17 @@ impl State {
 
         info!("End {}", lsp::request::Initialize::METHOD);
 
-        if let Err(e) = self.registerCMSource(&languageId, &result) {
+        if let Some(previous) = self.previous_capabilities.remove(&languageId) {
+            self.report_capabilities_changes(&languageId, &previous, &result)?;
+        }
+
+        let languageId = get_server_languageId(&languageId);
+        if let Err(e) = self.registerCMSource(languageId, &result) {
             let message = format!("LanguageClient: failed to register as NCM source: {}", e);
             error!("{}\n{:?}", message, e);
             self.echoerr(message)?;
         }
-        if let Err(e) = self.registerNCM2Source(&languageId, &result) {
+        if let Err(e) = self.registerNCM2Source(languageId, &result) {
             let message = format!("LanguageClient: failed to register as NCM source: {}", e);
             error!("{}\n{:?}", message, e);
             self.echoerr(message)?;
@@ -984,7 +2169,9 @@ impl State {
 
     fn initialized(&mut self, params: &Value) -> Result<()> {
         info!("Begin {}", lsp::notification::Initialized::METHOD);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
         self.notify(
             Some(&languageId),
             lsp::notification::Initialized::METHOD,
@@ -1013,17 +2200,22 @@ impl State {
             ],
             params,
         )?;
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::HoverRequest::METHOD)?;
 
-        let result = self.call(
-            Some(&languageId),
-            lsp::request::HoverRequest::METHOD,
-            TextDocumentPositionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
-            },
-        )?;
+        let cache_key = self.document_cache_key(&filename, &format!("{}:{}", line, character));
+        let result = match self.get_cached(CacheKind::Hover, &cache_key) {
+            Some(result) => result,
+            None => {
+                let result = self.call(
+                    Some(&languageId),
+                    lsp::request::HoverRequest::METHOD,
+                    self.text_document_position(&filename, line, character)?,
+                )?;
+                self.set_cached(CacheKind::Hover, cache_key, &result);
+                result
+            }
+        };
 
         if !handle {
             return Ok(result);
@@ -1070,13 +2262,10 @@ impl State {
             ],
             params,
         )?;
+        let languageId = self.route_server_key(&languageId, &filename, &method)?;
 
-        let params = serde_json::to_value(TextDocumentPositionParams {
-            text_document: TextDocumentIdentifier {
-                uri: filename.to_url()?,
-            },
-            position: Position { line, character },
-        })?.combine(params);
+        let position = self.text_document_position(&filename, line, character)?;
+        let params = serde_json::to_value(position)?.combine(params);
 
         let result = self.call(Some(&languageId), &method, &params)?;
 
@@ -1086,20 +2275,35 @@ impl State {
 
         let response: Option<GotoDefinitionResponse> = result.clone().to_lsp()?;
 
+        let found = match response {
+            Some(GotoDefinitionResponse::Scalar(_)) => true,
+            Some(GotoDefinitionResponse::Array(ref arr)) => !arr.is_empty(),
+            None => false,
+        };
+        if !found && self.grepFallback.is_some() {
+            self.grep_fallback(&languageId, &params)?;
+            return Ok(result);
+        }
+
         match response {
             None => {
                 self.echowarn("Not found!")?;
                 return Ok(Value::Null);
             }
             Some(GotoDefinitionResponse::Scalar(loc)) => {
-                self.edit(&goto_cmd, loc.uri.filepath()?)?;
-                self.cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+                if self.edit(&goto_cmd, loc.uri.filepath()?)? {
+                    self.cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+                }
             }
             Some(GotoDefinitionResponse::Array(arr)) => match arr.len() {
                 0 => self.echowarn("Not found!")?,
                 1 => {
-                    let loc = arr.get(0).ok_or_else(|| err_msg("Not found!"))?;
-                    self.edit(&goto_cmd, loc.uri.filepath()?)?;
+                    let loc = arr.get(0).ok_or_else(|| LCError::NotFound {
+                        what: "location".into(),
+                    })?;
+                    if !self.edit(&goto_cmd, loc.uri.filepath()?)? {
+                        return Ok(result);
+                    }
                     self.cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
                     let cur_file: String = self.eval("expand('%')")?;
                     self.echomsg_ellipsis(format!(
@@ -1145,6 +2349,8 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::Rename::METHOD)?;
 
         let mut new_name = new_name.unwrap_or_default();
         if new_name.is_empty() {
@@ -1154,15 +2360,16 @@ impl State {
         if new_name.is_empty() {
             return Ok(Value::Null);
         }
+        self.save_all()?;
 
+        let document_position = self.text_document_position(&filename, line, character)?;
+        let generation = self.document_generation(&filename);
         let result = self.call(
             Some(&languageId),
             lsp::request::Rename::METHOD,
             RenameParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
+                text_document: document_position.text_document,
+                position: document_position.position,
                 new_name,
             },
         )?;
@@ -1170,6 +2377,10 @@ impl State {
         if !handle || result == Value::Null {
             return Ok(result);
         }
+        if !self.is_document_current(&filename, generation) {
+            info!("Buffer unloaded while renaming. Dropping edits. filename: {}", filename);
+            return Ok(Value::Null);
+        }
 
         let edit: WorkspaceEdit = serde_json::from_value(result.clone())?;
         self.apply_WorkspaceEdit(&edit, params)?;
@@ -1196,17 +2407,37 @@ impl State {
         if !buftype.is_empty() {
             return Ok(Value::Null);
         }
-
-        let result = self.call(
-            Some(&languageId),
+        self.ensure_not_notebook(&filename, lsp::request::DocumentSymbolRequest::METHOD)?;
+        let languageId = self.route_server_key(
+            &languageId,
+            &filename,
             lsp::request::DocumentSymbolRequest::METHOD,
-            DocumentSymbolParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-            },
         )?;
 
+        let cache_key = self.document_cache_key(&filename, "");
+        let result = match self.get_cached(CacheKind::DocumentSymbols, &cache_key) {
+            Some(result) => result,
+            None => {
+                let result = self.call(
+                    Some(&languageId),
+                    lsp::request::DocumentSymbolRequest::METHOD,
+                    DocumentSymbolParams {
+                        text_document: TextDocumentIdentifier {
+                            uri: filename.to_url()?,
+                        },
+                    },
+                )?;
+                self.set_cached(CacheKind::DocumentSymbols, cache_key, &result);
+                result
+            }
+        };
+
+        if self.symbolIndex {
+            let symbols = flatten_document_symbols(&filename.to_url()?, &result)?;
+            self.symbol_index.insert(filename.clone(), symbols);
+            self.symbol_index_stale.remove(&filename);
+        }
+
         if !handle {
             return Ok(result);
         }
@@ -1252,6 +2483,113 @@ impl State {
         Ok(result)
     }
 
+    /// Format, and apply code actions of configured kinds, only to ranges changed since last save,
+    /// leaving untouched code as is. See g:LanguageClient_changedRangesOnSave.
+    pub fn languageClient_formatChangedRanges(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__FormatChangedRanges);
+        let (buftype, languageId, filename): (String, String, String) = self.gather_args(
+            &[VimVar::Buftype, VimVar::LanguageId, VimVar::Filename],
+            params,
+        )?;
+        let server_key = self.get_server_key(&languageId, &filename);
+        if !buftype.is_empty()
+            || languageId.is_empty()
+            || !self.writers.contains_key(&server_key)
+            || !self.text_documents.contains_key(&filename)
+            || self.notebook_documents.contains_key(&filename)
+        {
+            return Ok(Value::Null);
+        }
+
+        // Everything is changed in a file never saved.
+        let saved: Vec<String> = read_to_string(&filename)
+            .map(|content| content.lines().map(ToOwned::to_owned).collect())
+            .unwrap_or_default();
+        let args = json!({
+            VimVar::Buftype.to_key(): buftype,
+            VimVar::LanguageId.to_key(): languageId,
+            VimVar::Filename.to_key(): filename,
+            VimVar::Handle.to_key(): true,
+        });
+
+        for action in self.changedRangesOnSave.clone() {
+            // Ranges are recomputed after each action, which might have changed line count.
+            let lines: Vec<String> = self.call(None, "getline", json!([1, '$']))?;
+            let ranges = get_changed_ranges(&saved, &lines);
+            // From bottom to top, so that edits don't shift ranges yet to be processed.
+            for &(start, end) in ranges.iter().rev() {
+                if action == "formatting" {
+                    self.textDocument_rangeFormatting(
+                        &args.combine(&get_line_range_params(start, end)),
+                    )?;
+                } else {
+                    self.apply_range_code_action(&args, &action, start, end)?;
+                }
+            }
+        }
+
+        info!("End {}", REQUEST__FormatChangedRanges);
+        Ok(Value::Null)
+    }
+
+    /// Apply edit of first code action of kind for lines from start to end (exclusive).
+    fn apply_range_code_action(
+        &mut self,
+        params: &Value,
+        kind: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<()> {
+        self.textDocument_didChange(params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::CodeActionRequest::METHOD)?;
+
+        let diagnostics: Vec<_> = self
+            .diagnostics
+            .get(&filename.canonicalize())
+            .unwrap_or(&vec![])
+            .iter()
+            .filter(|dn| dn.range.start.line < end && dn.range.end.line >= start)
+            .cloned()
+            .collect();
+        let result: Value = self.call(
+            Some(&languageId),
+            lsp::request::CodeActionRequest::METHOD,
+            CodeActionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: filename.to_url()?,
+                },
+                range: Range {
+                    start: Position {
+                        line: start,
+                        character: 0,
+                    },
+                    end: Position {
+                        line: end,
+                        character: 0,
+                    },
+                },
+                context: CodeActionContext {
+                    diagnostics,
+                    only: Some(vec![kind.to_owned()]),
+                },
+            },
+        )?;
+
+        // Commands would need another round trip to language server, only edits are applied.
+        let edit = result
+            .as_array()
+            .and_then(|actions| actions.iter().filter_map(|action| action.get("edit")).next())
+            .cloned();
+        if let Some(edit) = edit {
+            let edit: WorkspaceEdit = serde_json::from_value(edit)?;
+            self.apply_WorkspaceEdit(&edit, params)?;
+        }
+        Ok(())
+    }
+
     pub fn textDocument_codeAction(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::CodeActionRequest::METHOD);
@@ -1276,6 +2614,12 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::CodeActionRequest::METHOD)?;
+
+        // Positions are relative to notebook cell, if any.
+        let document_position = self.text_document_position(&filename, line, character)?;
+        let offset = self.notebook_cell_offset(&filename, line);
 
         // Unify filename.
         let filename = filename.canonicalize();
@@ -1290,18 +2634,21 @@ impl State {
                 let end = dn.range.end;
                 (line, character) >= (start.line, start.character)
                     && (line, character) < (end.line, end.character)
-            }).cloned()
-            .collect();
+            }).map(|dn| {
+                let mut dn = dn.clone();
+                dn.range.start.line = dn.range.start.line.saturating_sub(offset);
+                dn.range.end.line = dn.range.end.line.saturating_sub(offset);
+                dn
+            }).collect();
+        let generation = self.document_generation(&filename);
         let result: Value = self.call(
             Some(&languageId),
             lsp::request::CodeActionRequest::METHOD,
             CodeActionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
+                text_document: document_position.text_document,
                 range: Range {
-                    start: Position { line, character },
-                    end: Position { line, character },
+                    start: document_position.position,
+                    end: document_position.position,
                 },
                 context: CodeActionContext {
                     diagnostics,
@@ -1310,11 +2657,17 @@ impl State {
             },
         )?;
 
-        let commands: Vec<Command> = serde_json::from_value(result.clone())?;
+        // Actions carry edits of the document as it was when requested.
+        if handle && !self.is_document_current(&filename, generation) {
+            info!("Buffer unloaded since request. Dropping code actions. filename: {}", filename);
+            return Ok(Value::Null);
+        }
+
+        let commands = get_code_action_commands(&result)?;
 
         let source: Vec<_> = commands
             .iter()
-            .map(|cmd| format!("{}: {}", cmd.command, cmd.title))
+            .map(|(cmd, _)| format!("{}: {}", cmd.command, cmd.title))
             .collect();
 
         self.update(|state| {
@@ -1357,17 +2710,23 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::Completion::METHOD)?;
 
-        let result = self.call(
-            Some(&languageId),
-            lsp::request::Completion::METHOD,
-            TextDocumentPositionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
-            },
-        )?;
+        let cache_key = self.document_cache_key(&filename, &format!("{}:{}", line, character));
+        let result = match self.get_cached(CacheKind::Completions, &cache_key) {
+            Some(result) => result,
+            None => {
+                let result = self.call(
+                    Some(&languageId),
+                    lsp::request::Completion::METHOD,
+                    self.text_document_position(&filename, line, character)?,
+                )?;
+                let result = shift_ranges(result, self.notebook_cell_offset(&filename, line));
+                self.set_cached(CacheKind::Completions, cache_key, &result);
+                result
+            }
+        };
 
         if !handle {
             return Ok(result);
@@ -1401,16 +2760,16 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId = self.route_server_key(
+            &languageId,
+            &filename,
+            lsp::request::SignatureHelpRequest::METHOD,
+        )?;
 
         let result = self.call(
             Some(&languageId),
             lsp::request::SignatureHelpRequest::METHOD,
-            TextDocumentPositionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
-            },
+            self.text_document_position(&filename, line, character)?,
         )?;
 
         if !handle || result == Value::Null {
@@ -1424,7 +2783,9 @@ impl State {
         let active_signature = help
             .signatures
             .get(help.active_signature.unwrap_or(0).to_usize()?)
-            .ok_or_else(|| err_msg("Failed to get active signature"))?;
+            .ok_or_else(|| LCError::NotFound {
+                what: "active signature".into(),
+            })?;
         let active_parameter: Option<&ParameterInformation>;
         if let Some(ref parameters) = active_signature.parameters {
             active_parameter = parameters.get(help.active_parameter.unwrap_or(0).to_usize()?);
@@ -1461,11 +2822,26 @@ impl State {
     pub fn textDocument_references(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", lsp::request::References::METHOD);
 
-        let (buftype, include_declaration): (String, bool) =
-            self.gather_args(&[VimVar::Buftype, VimVar::IncludeDeclaration], params)?;
+        let (buftype, include_declaration, handle): (String, bool, bool) = self.gather_args(
+            &[
+                VimVar::Buftype,
+                VimVar::IncludeDeclaration,
+                VimVar::Handle,
+            ],
+            params,
+        )?;
         if !buftype.is_empty() {
             return Ok(Value::Null);
         }
+        let (filter, filename, goto_cmd): (ReferencesFilter, String, Option<String>) = self
+            .gather_args(
+                &[
+                    ("filter", "{}"),
+                    ("filename", "LSP#filename()"),
+                    ("gotoCmd", "v:null"),
+                ],
+                params,
+            )?;
 
         let params = json!({
                 "method": lsp::request::References::METHOD,
@@ -1474,12 +2850,83 @@ impl State {
                 }
             }).combine(params);
 
-        let result = self.find_locations(&params)?;
+        let result = self.find_locations(&params.combine(&json!({ "handle": false })))?;
+
+        if !handle {
+            return Ok(result);
+        }
+
+        let locations: Option<Vec<Location>> = result.clone().to_lsp()?;
+        let locations = locations.unwrap_or_default();
+        if locations.is_empty() && self.grepFallback.is_some() {
+            let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], &params)?;
+            let languageId = self.get_server_key(&languageId, &filename);
+            self.grep_fallback(&languageId, &params)?;
+            return Ok(result);
+        }
+        // Access kinds are only known for references in current file.
+        let mut kinds = HashMap::new();
+        if filter.access.is_some() {
+            let highlights =
+                self.textDocument_documentHighlight(&params.combine(&json!({ "handle": false })))?;
+            let highlights: Option<Vec<DocumentHighlight>> = highlights.to_lsp()?;
+            for highlight in highlights.unwrap_or_default() {
+                let start = highlight.range.start;
+                kinds.insert((start.line, start.character), highlight.kind);
+            }
+        }
+        let references = locations
+            .into_iter()
+            .map(|loc| {
+                let kind = if loc.uri.filepath().ok() == Some(PathBuf::from(&filename)) {
+                    kinds
+                        .get(&(loc.range.start.line, loc.range.start.character))
+                        .cloned()
+                        .unwrap_or_default()
+                } else {
+                    None
+                };
+                (loc, kind)
+            }).collect();
+        self.update(|state| {
+            state.last_references = references;
+            Ok(())
+        })?;
+        self.display_references(&filter, &goto_cmd)?;
 
         info!("End {}", lsp::request::References::METHOD);
         Ok(result)
     }
 
+    fn display_references(
+        &mut self,
+        filter: &ReferencesFilter,
+        goto_cmd: &Option<String>,
+    ) -> Result<()> {
+        let cwd: String = self.eval("getcwd()")?;
+        let locations = filter_references(&self.last_references, filter, Path::new(&cwd))?;
+        match locations.len() {
+            0 => self.echowarn("Not found!")?,
+            1 => {
+                let loc = &locations[0];
+                if self.edit(goto_cmd, loc.uri.filepath()?)? {
+                    self.cursor(loc.range.start.line + 1, loc.range.start.character + 1)?;
+                }
+            }
+            _ => self.display_locations(&locations)?,
+        }
+        Ok(())
+    }
+
+    /// Apply filters to last found references, without querying language server again.
+    pub fn languageClient_filterReferences(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__FilterReferences);
+        let (filter,): (ReferencesFilter,) = self.gather_args(&[("filter", "{}")], params)?;
+        self.display_references(&filter, &None)?;
+        info!("End {}", REQUEST__FilterReferences);
+        Ok(Value::Null)
+    }
+
     pub fn textDocument_formatting(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::Formatting::METHOD);
@@ -1496,10 +2943,14 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        self.ensure_not_notebook(&filename, lsp::request::Formatting::METHOD)?;
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::Formatting::METHOD)?;
 
         let (tab_size, insert_spaces): (u64, u64) =
             self.eval(["shiftwidth()", "&expandtab"].as_ref())?;
         let insert_spaces = insert_spaces == 1;
+        let generation = self.document_generation(&filename);
         let result = self.call(
             Some(&languageId),
             lsp::request::Formatting::METHOD,
@@ -1518,6 +2969,10 @@ impl State {
         if !handle {
             return Ok(result);
         }
+        if !self.is_document_current(&filename, generation) {
+            info!("Buffer unloaded while formatting. Dropping edits. filename: {}", filename);
+            return Ok(Value::Null);
+        }
 
         let text_edits: Option<Vec<TextEdit>> = serde_json::from_value(result.clone())?;
         let text_edits = text_edits.unwrap_or_default();
@@ -1530,6 +2985,42 @@ impl State {
         Ok(result)
     }
 
+    /// Show result of formatting side by side with current buffer in diff mode, leaving the
+    /// buffer untouched.
+    pub fn languageClient_previewFormatting(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__PreviewFormatting);
+        let (buftype, text): (String, Vec<String>) =
+            self.gather_args(&[VimVar::Buftype, VimVar::Text], params)?;
+        if !buftype.is_empty() {
+            return Ok(Value::Null);
+        }
+
+        let result = self.textDocument_formatting(&params.combine(&json!({ "handle": false })))?;
+        let edits: Option<Vec<TextEdit>> = serde_json::from_value(result.clone())?;
+        let mut edits = edits.unwrap_or_default();
+        if edits.is_empty() {
+            self.echo("No formatting changes.")?;
+            return Ok(result);
+        }
+
+        // Same order as in apply_TextEdits.
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        edits.reverse();
+        let mut lines = text;
+        let fixendofline = self.eval::<_, u8>("&fixendofline")? == 1;
+        if lines.last().map(String::is_empty) == Some(false) && fixendofline {
+            lines.push("".to_owned());
+        }
+        let mut lines = apply_TextEdits(&lines, &edits)?;
+        if lines.last().map(String::is_empty) == Some(true) && fixendofline {
+            lines.pop();
+        }
+
+        self.notify(None, "s:PreviewFormatting", json!([lines]))?;
+        info!("End {}", REQUEST__PreviewFormatting);
+        Ok(result)
+    }
+
     pub fn textDocument_rangeFormatting(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::RangeFormatting::METHOD);
@@ -1554,10 +3045,14 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        self.ensure_not_notebook(&filename, lsp::request::RangeFormatting::METHOD)?;
+        let languageId =
+            self.route_server_key(&languageId, &filename, lsp::request::RangeFormatting::METHOD)?;
 
         let (tab_size, insert_spaces): (u64, u64) =
             self.eval(["shiftwidth()", "&expandtab"].as_ref())?;
         let insert_spaces = insert_spaces == 1;
+        let generation = self.document_generation(&filename);
         let result = self.call(
             Some(&languageId),
             lsp::request::RangeFormatting::METHOD,
@@ -1586,6 +3081,10 @@ impl State {
         if !handle {
             return Ok(result);
         }
+        if !self.is_document_current(&filename, generation) {
+            info!("Buffer unloaded while formatting. Dropping edits. filename: {}", filename);
+            return Ok(Value::Null);
+        }
 
         let text_edits: Option<Vec<TextEdit>> = serde_json::from_value(result.clone())?;
         let text_edits = text_edits.unwrap_or_default();
@@ -1601,16 +3100,24 @@ impl State {
     pub fn completionItem_resolve(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::ResolveCompletionItem::METHOD);
-        let (buftype, languageId, handle): (String, String, bool) = self.gather_args(
-            &[VimVar::Buftype, VimVar::LanguageId, VimVar::Handle],
-            params,
-        )?;
+        let (buftype, languageId, filename, handle): (String, String, String, bool) = self
+            .gather_args(
+                &[
+                    VimVar::Buftype,
+                    VimVar::LanguageId,
+                    VimVar::Filename,
+                    VimVar::Handle,
+                ],
+                params,
+            )?;
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId = self.get_server_key(&languageId, &filename);
         let (completion_item,): (CompletionItem,) =
             self.gather_args(&["completionItem"], params)?;
 
+        let generation = self.document_generation(&filename);
         let result = self.call(
             Some(&languageId),
             lsp::request::ResolveCompletionItem::METHOD,
@@ -1620,6 +3127,13 @@ impl State {
         if !handle {
             return Ok(result);
         }
+        if !self.is_document_current(&filename, generation) {
+            info!(
+                "Buffer unloaded since request. Dropping completion item. filename: {}",
+                filename
+            );
+            return Ok(Value::Null);
+        }
 
         // TODO: proper integration.
         let msg = format!("comletionItem/resolve result not handled: {:?}", result);
@@ -1633,20 +3147,61 @@ impl State {
     pub fn workspace_symbol(&mut self, params: &Value) -> Result<Value> {
         self.textDocument_didChange(params)?;
         info!("Begin {}", lsp::request::WorkspaceSymbol::METHOD);
-        let (buftype, languageId, handle): (String, String, bool) = self.gather_args(
-            &[VimVar::Buftype, VimVar::LanguageId, VimVar::Handle],
-            params,
-        )?;
+        let (buftype, languageId, filename, handle): (String, String, String, bool) = self
+            .gather_args(
+                &[
+                    VimVar::Buftype,
+                    VimVar::LanguageId,
+                    VimVar::Filename,
+                    VimVar::Handle,
+                ],
+                params,
+            )?;
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId = self.get_server_key(&languageId, &filename);
 
         let (query,): (String,) = self.gather_args(&[("query", "")], params)?;
-        let result = self.call(
-            Some(&languageId),
-            lsp::request::WorkspaceSymbol::METHOD,
-            WorkspaceSymbolParams { query },
-        )?;
+        if self.symbolIndex {
+            self.refresh_symbol_index()?;
+        }
+        // Workspace symbol search of a server still indexing is slow and incomplete anyway.
+        let indexing = self
+            .progress
+            .get(&languageId)
+            .map_or(false, |progress| !progress.is_empty());
+        let cache_key = format!("{}:{}", languageId, query);
+        let result = match self.get_cached(CacheKind::Symbols, &cache_key) {
+            Some(result) => result,
+            None if self.symbolIndex && indexing => {
+                info!("Server is busy. Answering from symbol index.");
+                Value::Null
+            }
+            None => {
+                let result = self.call(
+                    Some(&languageId),
+                    lsp::request::WorkspaceSymbol::METHOD,
+                    WorkspaceSymbolParams {
+                        query: query.clone(),
+                    },
+                );
+                let result = match result {
+                    Err(ref err) if self.symbolIndex => {
+                        warn!("Falling back to symbol index: {}", err);
+                        Value::Null
+                    }
+                    result => result?,
+                };
+                self.set_cached(CacheKind::Symbols, cache_key, &result);
+                result
+            }
+        };
+        let result = if self.symbolIndex {
+            self.merge_symbol_index(&languageId, &query, result)?
+        } else {
+            result
+        };
 
         if !handle {
             return Ok(result);
@@ -1700,9 +3255,12 @@ impl State {
 
     pub fn workspace_executeCommand(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", lsp::request::ExecuteCommand::METHOD);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
         let (command, arguments): (String, Vec<Value>) =
             self.gather_args(&["command", "arguments"], params)?;
+        self.save_all()?;
 
         let result = self.call(
             Some(&languageId),
@@ -1716,7 +3274,8 @@ impl State {
     pub fn workspace_applyEdit(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", lsp::request::ApplyWorkspaceEdit::METHOD);
 
-        let params: ApplyWorkspaceEditParams = params.clone().to_lsp()?;
+        let params: ApplyWorkspaceEditParams =
+            self.map_notebook_cells(params.clone()).to_lsp()?;
         self.apply_WorkspaceEdit(&params.edit, &Value::Null)?;
 
         info!("End {}", lsp::request::ApplyWorkspaceEdit::METHOD);
@@ -1745,48 +3304,89 @@ impl State {
 
     pub fn textDocument_didOpen(&mut self, params: &Value) -> Result<()> {
         info!("Begin {}", lsp::notification::DidOpenTextDocument::METHOD);
-        let (buftype, languageId, filename, text): (String, String, String, Vec<String>) = self
-            .gather_args(
-                &[
-                    VimVar::Buftype,
-                    VimVar::LanguageId,
-                    VimVar::Filename,
-                    VimVar::Text,
-                ],
-                params,
-            )?;
+        let (buftype, languageId, filename, text, fileformat, diff): (
+            String,
+            String,
+            String,
+            Vec<String>,
+            String,
+            u8,
+        ) = self.gather_args(
+            &[
+                VimVar::Buftype,
+                VimVar::LanguageId,
+                VimVar::Filename,
+                VimVar::Text,
+                VimVar::Fileformat,
+                VimVar::Diff,
+            ],
+            params,
+        )?;
 
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(());
         }
 
+        if self.is_outside_root(&languageId, &filename)? {
+            info!("File is outside of project root. filename: {}", filename);
+            return Ok(());
+        }
+        let server_key = self.get_server_key(&languageId, &filename);
+
         let text_document = TextDocumentItem {
             uri: filename.to_url()?,
             language_id: languageId.clone(),
             version: 0,
-            text: text.join("\n"),
+            text: text.join(get_line_ending(&fileformat)),
         };
 
         self.update(|state| {
+            state.last_generation += 1;
+            state
+                .document_generations
+                .insert(filename.clone(), state.last_generation);
             Ok(state
                 .text_documents
                 .insert(filename.clone(), text_document.clone()))
         })?;
+        // Files are opened in diff mode or with conflict markers too, e.g., by git mergetool.
+        self.update_suspension(&filename, &text, diff == 1)?;
+        if self.is_save_command_only(&languageId) {
+            info!("No language server for {}, document tracked only.", languageId);
+            return Ok(());
+        }
 
+        if self.is_notebook(&server_key, &filename) {
+            self.notebook_didOpen(&server_key, &filename, &text_document)?;
+        } else {
+            self.notify(
+                Some(&server_key),
+                lsp::notification::DidOpenTextDocument::METHOD,
+                DidOpenTextDocumentParams { text_document },
+            )?;
+        }
+
+        self.notify(None, "s:SetOmnifunc", json!([]))?;
+        // For automatic completion, see g:LanguageClient_autoComplete.
+        let trigger_characters = self
+            .capabilities
+            .get(&server_key)
+            .map(|result| result["capabilities"]["completionProvider"]["triggerCharacters"].clone())
+            .filter(Value::is_array)
+            .unwrap_or_else(|| json!([]));
         self.notify(
-            Some(&languageId),
-            lsp::notification::DidOpenTextDocument::METHOD,
-            DidOpenTextDocumentParams { text_document },
+            None,
+            "setbufvar",
+            json!([filename, "LanguageClient_triggerCharacters", trigger_characters]),
         )?;
-
-        self.command("setlocal omnifunc=LanguageClient#complete")?;
-        let root = self.roots.get(&languageId).cloned().unwrap_or_default();
+        let root = self.roots.get(&server_key).cloned().unwrap_or_default();
         self.notify(
             None,
             "setbufvar",
             json!([filename, "LanguageClient_projectRoot", root]),
         )?;
         self.notify(None, "s:ExecuteAutocmd", "LanguageClientBufReadPost")?;
+        self.update_symbol_index(&filename);
 
         info!("End {}", lsp::notification::DidOpenTextDocument::METHOD);
         Ok(())
@@ -1801,21 +3401,35 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(());
         }
+        let save_command_only = self.is_save_command_only(&languageId);
+        let languageId = self.get_server_key(&languageId, &filename);
         if !self.get(|state| Ok(state.text_documents.contains_key(&filename)))? {
             info!("Not opened yet. Switching to didOpen.");
             return self.textDocument_didOpen(params);
         }
 
-        let (text,): (Vec<String>,) = self.gather_args(&[VimVar::Text], params)?;
+        let (text, fileformat, diff): (Vec<String>, String, u8) =
+            self.gather_args(&[VimVar::Text, VimVar::Fileformat, VimVar::Diff], params)?;
+
+        self.update_suspension(&filename, &text, diff == 1)?;
+        if self.suspendOnConflict == SuspendOnConflict::Sync
+            && self.suspended_documents.contains(&filename)
+        {
+            info!("Changes suspended due to diff mode or merge conflict.");
+            return Ok(());
+        }
 
-        let text = text.join("\n");
+        // Keep line endings as in file, so that server sees the same content as on disk.
+        let text = text.join(get_line_ending(&fileformat));
         let text_state = self
             .get(|state| {
                 state
                     .text_documents
                     .get(&filename)
                     .ok_or_else(|| {
-                        format_err!("TextDocumentItem not found! filename: {}", filename)
+                        LCError::DocumentNotOpened {
+                            filename: filename.clone(),
+                        }.into()
                     }).map(|doc| doc.text.clone())
             }).unwrap_or_default();
         if text == text_state {
@@ -1825,7 +3439,9 @@ impl State {
 
         let version = self.update(|state| {
             let document = state.text_documents.get_mut(&filename).ok_or_else(|| {
-                format_err!("Failed to get TextDocumentItem! filename: {}", filename)
+                LCError::DocumentNotOpened {
+                    filename: filename.clone(),
+                }
             })?;
 
             let version = document.version + 1;
@@ -1841,6 +3457,14 @@ impl State {
             }
             Ok(version)
         })?;
+        self.invalidate_cached(&filename);
+        if save_command_only {
+            return Ok(());
+        }
+
+        if self.notebook_documents.contains_key(&filename) {
+            return self.notebook_didChange(&languageId, &filename, version, &text);
+        }
 
         self.notify(
             Some(&languageId),
@@ -1871,20 +3495,158 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(());
         }
+        self.run_save_commands(&languageId, &filename)?;
+        if self.is_save_command_only(&languageId) {
+            return Ok(());
+        }
+        let languageId = self.get_server_key(&languageId, &filename);
         let uri = filename.to_url()?;
 
-        self.notify(
-            Some(&languageId),
-            lsp::notification::DidSaveTextDocument::METHOD,
-            DidSaveTextDocumentParams {
-                text_document: TextDocumentIdentifier { uri: uri.clone() },
-            },
-        )?;
+        if self.notebook_documents.contains_key(&filename) {
+            self.notify(
+                Some(&languageId),
+                NOTIFICATION__NotebookDidSave,
+                json!({ "notebookDocument": { "uri": uri.as_str() } }),
+            )?;
+        } else {
+            self.notify(
+                Some(&languageId),
+                lsp::notification::DidSaveTextDocument::METHOD,
+                DidSaveTextDocumentParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                },
+            )?;
+        }
+        self.update_symbol_index(&filename);
 
         info!("End {}", lsp::notification::DidSaveTextDocument::METHOD);
         Ok(())
     }
 
+    /// Run g:LanguageClient_saveCommands of language in background. Outputs are sent back as
+    /// NOTIFICATION__SaveCommandOutput.
+    fn run_save_commands(&mut self, languageId: &str, filename: &str) -> Result<()> {
+        let commands = match self.saveCommands.get(languageId) {
+            Some(commands) => commands.clone(),
+            None => return Ok(()),
+        };
+        let root = get_rootPath(Path::new(filename), languageId, &self.rootMarkers)?
+            .to_string_lossy()
+            .into_owned();
+        let placeholders = hashmap!{
+            "file" => filename.to_owned(),
+            "rootPath" => root.clone(),
+        };
+
+        for save_command in commands {
+            let command = expand_command_placeholders(&save_command.command, &placeholders, false);
+            let source = match command.get(0) {
+                Some(program) => program.clone(),
+                None => continue,
+            };
+            let tx = self.tx.clone();
+            let filename = filename.to_owned();
+            let root = root.clone();
+            thread::Builder::new()
+                .name(format!("save-command-{}", source))
+                .spawn(move || {
+                    info!("Running save command: {:?}", command);
+                    let output = match std::process::Command::new(&source)
+                        .args(&command[1..])
+                        .current_dir(&root)
+                        .output()
+                    {
+                        Ok(output) => output,
+                        Err(err) => {
+                            warn!("Failed to run save command ({:?}): {}", command, err);
+                            return;
+                        }
+                    };
+                    let lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .chain(String::from_utf8_lossy(&output.stderr).lines())
+                        .map(ToOwned::to_owned)
+                        .collect();
+                    let _ = tx.send(Message::Notification(
+                        None,
+                        rpc::Notification {
+                            jsonrpc: None,
+                            method: NOTIFICATION__SaveCommandOutput.into(),
+                            params: json!({
+                                "filename": filename,
+                                "root": root,
+                                "source": source,
+                                "errorformat": save_command.errorformat,
+                                "lines": lines,
+                            }).to_params()
+                            .unwrap_or_default(),
+                        },
+                    ));
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge output of a g:LanguageClient_saveCommands command into diagnostics of file,
+    /// replacing those from previous run of the same command.
+    pub fn languageClient_saveCommandOutput(&mut self, params: &Value) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__SaveCommandOutput);
+        let (filename, root, source, errorformat, lines): (
+            String,
+            String,
+            String,
+            Option<String>,
+            Vec<String>,
+        ) = self.gather_args(
+            &["filename", "root", "source", "errorformat", "lines"],
+            params,
+        )?;
+        if !self.get(|state| Ok(state.diagnosticsEnable))? {
+            return Ok(());
+        }
+
+        let filename = filename.canonicalize();
+        let entries: Vec<QuickfixEntry> = self.call(
+            None,
+            "LanguageClient#parseErrorformat",
+            json!([lines, errorformat, root]),
+        )?;
+        let diagnostics: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.filename.canonicalize() == filename)
+            .map(|entry| quickfix_entry_to_diagnostic(entry, &source))
+            .collect();
+
+        let previous = self
+            .external_diagnostics
+            .entry(filename.clone())
+            .or_insert_with(HashMap::new)
+            .insert(source, diagnostics.clone())
+            .unwrap_or_default();
+        let mut merged: Vec<_> = self
+            .diagnostics
+            .get(&filename)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|diagnostic| !previous.contains(diagnostic))
+            .collect();
+        merged.extend(diagnostics);
+        self.diagnostics.insert(filename.clone(), merged.clone());
+        self.update_quickfixlist()?;
+
+        let current_filename: String = self.eval(VimVar::Filename)?;
+        if filename == current_filename.canonicalize() {
+            self.process_diagnostics(&current_filename, &merged)?;
+            self.languageClient_handleCursorMoved(&Value::Null)?;
+            self.notify(None, "s:ExecuteAutocmd", "LanguageClientDiagnosticsChanged")?;
+        }
+
+        info!("End {}", NOTIFICATION__SaveCommandOutput);
+        Ok(())
+    }
+
     pub fn textDocument_didClose(&mut self, params: &Value) -> Result<()> {
         info!("Begin {}", lsp::notification::DidCloseTextDocument::METHOD);
         let (buftype, languageId, filename): (String, String, String) = self.gather_args(
@@ -1894,20 +3656,343 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(());
         }
+        let save_command_only = self.is_save_command_only(&languageId);
+        let languageId = self.get_server_key(&languageId, &filename);
+        self.document_generations.remove(&filename);
+        self.external_diagnostics.remove(&filename.canonicalize());
+        self.symbol_index.remove(&filename);
+        self.symbol_index_stale.remove(&filename);
+        if save_command_only {
+            return Ok(());
+        }
+
+        if let Some(notebook) = self.notebook_documents.remove(&filename) {
+            let uri = filename.to_url()?;
+            let cells: Vec<_> = (0..notebook.cells.len())
+                .map(|i| json!({ "uri": get_notebook_cell_uri(&uri, i).as_str() }))
+                .collect();
+            self.notify(
+                Some(&languageId),
+                NOTIFICATION__NotebookDidClose,
+                json!({
+                    "notebookDocument": { "uri": uri.as_str() },
+                    "cellTextDocuments": cells,
+                }),
+            )?;
+        } else {
+            self.notify(
+                Some(&languageId),
+                lsp::notification::DidCloseTextDocument::METHOD,
+                DidCloseTextDocumentParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                },
+            )?;
+        }
+
+        let synced_keys: Vec<String> = self
+            .synced_documents
+            .iter_mut()
+            .filter_map(|(key, documents)| documents.remove(&filename).map(|_| key.clone()))
+            .collect();
+        for key in synced_keys {
+            self.notify(
+                Some(&key),
+                lsp::notification::DidCloseTextDocument::METHOD,
+                DidCloseTextDocumentParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                },
+            )?;
+        }
+        info!("End {}", lsp::notification::DidCloseTextDocument::METHOD);
+        Ok(())
+    }
+
+    /// Suspend diagnostics, and sync if so configured, while buffer is in diff mode or has merge
+    /// conflict markers, as language servers tend to report loads of errors meanwhile. Resume once
+    /// the condition clears.
+    fn update_suspension(&mut self, filename: &str, text: &[String], diff: bool) -> Result<()> {
+        let suspend = self.suspendOnConflict != SuspendOnConflict::Off
+            && (diff || has_conflict_markers(text));
+        if suspend == self.suspended_documents.contains(filename) {
+            return Ok(());
+        }
+
+        if suspend {
+            info!("Suspending diagnostics. filename: {}", filename);
+            self.suspended_documents.insert(filename.to_owned());
+        } else {
+            info!("Resuming diagnostics. filename: {}", filename);
+            self.suspended_documents.remove(filename);
+        }
+        let diagnostics = self.diagnostics.get(filename).cloned().unwrap_or_default();
+        self.process_diagnostics(filename, &diagnostics)?;
+        self.languageClient_handleCursorMoved(&Value::Null)?;
+        Ok(())
+    }
+
+    /// Generation of an opened document, renewed every time it's opened. Responses to requests sent
+    /// for a generation no longer current are late, and dropped instead of applied to the buffer.
+    fn document_generation(&self, filename: &str) -> Option<u64> {
+        self.document_generations.get(filename).cloned()
+    }
+
+    /// Whether document is still the one a request was sent for, i.e., it's neither closed, nor to
+    /// be closed by a message from vim queued while waiting for the response.
+    fn is_document_current(&self, filename: &str, generation: Option<u64>) -> bool {
+        let closing = self.pending_calls.iter().any(|call| match *call {
+            Call::Notification(None, ref notification) => {
+                (notification.method == NOTIFICATION__HandleBufDelete
+                    || notification.method == lsp::notification::DidCloseTextDocument::METHOD)
+                    && serde_json::to_value(&notification.params)
+                        .map(|params| params["filename"] == filename)
+                        .unwrap_or(false)
+            }
+            _ => false,
+        });
+        generation.is_some() && !closing && self.document_generation(filename) == generation
+    }
+
+    /// Whether file is to be synced as notebook, i.e., it's a notebook file and language server
+    /// supports notebook document sync.
+    fn is_notebook(&self, languageId: &str, filename: &str) -> bool {
+        filename.ends_with(".ipynb") && self.has_capability(languageId, "notebookDocumentSync")
+    }
+
+    fn notebook_cells(&self, language_id: &str, text: &str) -> Vec<(u64, String)> {
+        match self.notebookCellMarkers.get(language_id) {
+            Some(marker) => split_notebook_cells(text, marker),
+            None => vec![(0, text.to_owned())],
+        }
+    }
+
+    fn notebook_didOpen(
+        &mut self,
+        languageId: &str,
+        filename: &str,
+        text_document: &TextDocumentItem,
+    ) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__NotebookDidOpen);
+        let cells = self.notebook_cells(&text_document.language_id, &text_document.text);
+        let uri = &text_document.uri;
+        let cell_documents: Vec<_> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, ref text))| TextDocumentItem {
+                uri: get_notebook_cell_uri(uri, i),
+                language_id: text_document.language_id.clone(),
+                version: text_document.version,
+                text: text.clone(),
+            }).collect();
 
         self.notify(
-            Some(&languageId),
-            lsp::notification::DidCloseTextDocument::METHOD,
-            DidCloseTextDocumentParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
+            Some(languageId),
+            NOTIFICATION__NotebookDidOpen,
+            json!({
+                "notebookDocument": {
+                    "uri": uri.as_str(),
+                    "notebookType": "jupyter-notebook",
+                    "version": text_document.version,
+                    "cells": cell_documents
+                        .iter()
+                        .map(|cell| json!({ "kind": 2, "document": cell.uri.as_str() }))
+                        .collect::<Vec<_>>(),
                 },
+                "cellTextDocuments": cell_documents,
+            }),
+        )?;
+
+        self.notebook_documents.insert(
+            filename.to_owned(),
+            NotebookDocument {
+                version: text_document.version,
+                language_id: text_document.language_id.clone(),
+                cells,
+                diagnostics: HashMap::new(),
             },
+        );
+        info!("End {}", NOTIFICATION__NotebookDidOpen);
+        Ok(())
+    }
+
+    /// Sync changed buffer as changes of cell contents, plus cells added or removed at the end.
+    fn notebook_didChange(
+        &mut self,
+        languageId: &str,
+        filename: &str,
+        version: u64,
+        text: &str,
+    ) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__NotebookDidChange);
+        let notebook = self
+            .notebook_documents
+            .get(filename)
+            .cloned()
+            .ok_or_else(|| LCError::DocumentNotOpened {
+                filename: filename.to_owned(),
+            })?;
+        let cells = self.notebook_cells(&notebook.language_id, text);
+        let uri = filename.to_url()?;
+        let common = std::cmp::min(cells.len(), notebook.cells.len());
+
+        let text_content: Vec<_> = (0..common)
+            .filter(|&i| cells[i].1 != notebook.cells[i].1)
+            .map(|i| {
+                json!({
+                    "document": {
+                        "uri": get_notebook_cell_uri(&uri, i).as_str(),
+                        "version": version,
+                    },
+                    "changes": [{ "text": cells[i].1 }],
+                })
+            }).collect();
+        let opened: Vec<_> = (common..cells.len())
+            .map(|i| TextDocumentItem {
+                uri: get_notebook_cell_uri(&uri, i),
+                language_id: notebook.language_id.clone(),
+                version,
+                text: cells[i].1.clone(),
+            }).collect();
+        let closed: Vec<_> = (common..notebook.cells.len())
+            .map(|i| json!({ "uri": get_notebook_cell_uri(&uri, i).as_str() }))
+            .collect();
+
+        let mut change = json!({ "cells": { "textContent": text_content } });
+        if !opened.is_empty() || !closed.is_empty() {
+            change["cells"]["structure"] = json!({
+                "array": {
+                    "start": common,
+                    "deleteCount": closed.len(),
+                    "cells": opened
+                        .iter()
+                        .map(|cell| json!({ "kind": 2, "document": cell.uri.as_str() }))
+                        .collect::<Vec<_>>(),
+                },
+                "didOpen": opened,
+                "didClose": closed,
+            });
+        }
+
+        self.notify(
+            Some(languageId),
+            NOTIFICATION__NotebookDidChange,
+            json!({
+                "notebookDocument": { "uri": uri.as_str(), "version": version },
+                "change": change,
+            }),
         )?;
-        info!("End {}", lsp::notification::DidCloseTextDocument::METHOD);
+
+        if let Some(notebook) = self.notebook_documents.get_mut(filename) {
+            notebook.version = version;
+            let cell_count = cells.len();
+            notebook.cells = cells;
+            notebook.diagnostics.retain(|&i, _| i < cell_count);
+        }
+        info!("End {}", NOTIFICATION__NotebookDidChange);
+        Ok(())
+    }
+
+    /// Position params, mapped to cell text document if file is synced as notebook.
+    fn text_document_position(
+        &self,
+        filename: &str,
+        line: u64,
+        character: u64,
+    ) -> Result<TextDocumentPositionParams> {
+        let uri = filename.to_url()?;
+        if let Some(notebook) = self.notebook_documents.get(filename) {
+            if let Some(i) = notebook.cells.iter().rposition(|&(start, _)| start <= line) {
+                return Ok(TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: get_notebook_cell_uri(&uri, i),
+                    },
+                    position: Position {
+                        line: line - notebook.cells[i].0,
+                        character,
+                    },
+                });
+            }
+        }
+
+        Ok(TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position { line, character },
+        })
+    }
+
+    /// Offset of cell containing line in notebook buffer, i.e., line of buffer where positions in
+    /// responses to `text_document_position` requests start. 0 for files that are not notebooks.
+    fn notebook_cell_offset(&self, filename: &str, line: u64) -> u64 {
+        self.notebook_documents
+            .get(filename)
+            .and_then(|notebook| {
+                notebook
+                    .cells
+                    .iter()
+                    .rev()
+                    .map(|&(start, _)| start)
+                    .find(|&start| start <= line)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Map locations and edits in cell text documents back to notebook buffers.
+    pub fn map_notebook_cells(&self, value: Value) -> Value {
+        if self.notebook_documents.is_empty() {
+            return value;
+        }
+
+        let notebooks = self
+            .notebook_documents
+            .iter()
+            .filter_map(|(filename, notebook)| {
+                let uri = filename.to_url().ok()?;
+                let starts = notebook.cells.iter().map(|&(start, _)| start).collect();
+                Some((uri.into_string(), starts))
+            })
+            .collect();
+        map_notebook_cell_uris(value, &notebooks)
+    }
+
+    /// Whole-document requests are not mapped to cells, refuse those for notebooks.
+    pub fn ensure_not_notebook(&self, filename: &str, method: &str) -> Result<()> {
+        if self.notebook_documents.contains_key(filename) {
+            return Err(LCError::NotSupportedInNotebook {
+                method: method.to_owned(),
+            }.into());
+        }
         Ok(())
     }
 
+    /// Diagnostics of notebook, merged from diagnostics of cells, in buffer coordinates.
+    fn merge_notebook_diagnostics(
+        &mut self,
+        filename: &str,
+        index: usize,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let notebook = match self.notebook_documents.get_mut(filename) {
+            Some(notebook) => notebook,
+            None => return vec![],
+        };
+        notebook.diagnostics.insert(index, diagnostics);
+
+        let mut merged = vec![];
+        for (i, diagnostics) in &notebook.diagnostics {
+            let offset = notebook.cells.get(*i).map(|cell| cell.0).unwrap_or_default();
+            for diagnostic in diagnostics {
+                let mut diagnostic = diagnostic.clone();
+                diagnostic.range.start.line += offset;
+                diagnostic.range.end.line += offset;
+                merged.push(diagnostic);
+            }
+        }
+        merged
+    }
+
     pub fn textDocument_publishDiagnostics(&mut self, params: &Value) -> Result<()> {
         info!("Begin {}", lsp::notification::PublishDiagnostics::METHOD);
         let params: PublishDiagnosticsParams = params.clone().to_lsp()?;
@@ -1923,7 +4008,13 @@ impl State {
         // Unify name to avoid mismatch due to case insensitivity.
         let filename = filename.canonicalize();
 
-        let diagnostics = params.diagnostics;
+        let mut diagnostics = match get_notebook_cell_index(&params.uri) {
+            Some(index) => self.merge_notebook_diagnostics(&filename, index, params.diagnostics),
+            None => params.diagnostics,
+        };
+        if let Some(external) = self.external_diagnostics.get(&filename) {
+            diagnostics.extend(external.values().flat_map(|d| d.iter().cloned()));
+        }
 
         self.diagnostics
             .insert(filename.clone(), diagnostics.clone());
@@ -1998,7 +4089,10 @@ impl State {
             }
         }
 
-        self.registrations.extend(params.registrations);
+        self.registrations
+            .entry(languageId.to_owned())
+            .or_insert_with(Vec::new)
+            .extend(params.registrations);
         info!("End {}", lsp::request::RegisterCapability::METHOD);
         Ok(Value::Null)
     }
@@ -2011,13 +4105,14 @@ impl State {
         info!("Begin {}", lsp::request::UnregisterCapability::METHOD);
         let params: UnregistrationParams = params.clone().to_lsp()?;
         let mut regs_removed = vec![];
-        for r in &params.unregisterations {
-            if let Some(idx) = self
-                .registrations
-                .iter()
-                .position(|i| i.id == r.id && i.method == r.method)
-            {
-                regs_removed.push(self.registrations.swap_remove(idx));
+        if let Some(registrations) = self.registrations.get_mut(languageId) {
+            for r in &params.unregisterations {
+                if let Some(idx) = registrations
+                    .iter()
+                    .position(|i| i.id == r.id && i.method == r.method)
+                {
+                    regs_removed.push(registrations.swap_remove(idx));
+                }
             }
         }
 
@@ -2042,20 +4137,99 @@ impl State {
         Ok(Value::Null)
     }
 
-    pub fn exit(&mut self, params: &Value) -> Result<()> {
-        info!("Begin {}", lsp::notification::Exit::METHOD);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+    /// Request code lenses again for documents of server shown in any window, and show them
+    /// on lines they belong to.
+    fn refresh_code_lenses(&mut self, languageId: &str, filenames: &[String]) -> Result<()> {
+        if !self.has_capability(languageId, "codeLensProvider") {
+            return Ok(());
+        }
+        let visible: Vec<String> =
+            self.eval("map(getwininfo(), 'fnamemodify(bufname(v:val.bufnr), \":p\")')")?;
+        for filename in filenames {
+            if !visible.contains(filename) || self.notebook_documents.contains_key(filename) {
+                continue;
+            }
+            let result: Value = self.call(
+                Some(languageId),
+                lsp::request::CodeLensRequest::METHOD,
+                CodeLensParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: filename.to_url()?,
+                    },
+                },
+            )?;
+            // Titles of lenses on the same line are joined. Unresolved lenses have none yet.
+            let mut lines: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+            for lens in result.as_array().cloned().unwrap_or_default() {
+                if let (Some(line), Some(title)) = (
+                    lens["range"]["start"]["line"].as_u64(),
+                    lens["command"]["title"].as_str(),
+                ) {
+                    lines.entry(line).or_insert_with(Vec::new).push(title.to_owned());
+                }
+            }
+            let lenses: Vec<_> = lines
+                .into_iter()
+                .map(|(line, titles)| json!([line, titles.join(" | ")]))
+                .collect();
+            self.notify(None, "s:SetCodeLenses", json!([filename, lenses]))?;
+        }
+        Ok(())
+    }
 
-        let result = self.notify(
-            Some(&languageId),
-            lsp::notification::Exit::METHOD,
-            Value::Null,
-        );
-        if let Err(err) = result {
-            error!("Error: {:?}", err);
+    /// Handle workspace/*/refresh requests from language server.
+    ///
+    /// Drop cached responses of documents served by the language server, then notify vim so
+    /// that the data could be requested again for visible buffers.
+    pub fn workspace_refresh(&mut self, languageId: &str, method: &str) -> Result<Value> {
+        info!("Begin {}", method);
+        let root = self.roots.get(languageId).cloned().unwrap_or_default();
+        let filenames: Vec<String> = self
+            .text_documents
+            .keys()
+            .filter(|filename| filename.starts_with(&root))
+            .cloned()
+            .collect();
+        for filename in &filenames {
+            self.invalidate_cached(filename);
+        }
+
+        let event = match method {
+            REQUEST__CodeLensRefresh => "LanguageClientCodeLensRefresh",
+            REQUEST__SemanticTokensRefresh => "LanguageClientSemanticTokensRefresh",
+            REQUEST__InlayHintRefresh => "LanguageClientInlayHintRefresh",
+            REQUEST__DiagnosticRefresh => "LanguageClientDiagnosticsRefresh",
+            _ => {
+                return Err(LCError::MethodNotFound {
+                    method: method.to_owned(),
+                }.into())
+            }
+        };
+        if method == REQUEST__CodeLensRefresh {
+            self.refresh_code_lenses(languageId, &filenames)?;
         }
-        if let Err(err) = self.cleanup(&languageId) {
-            error!("Error: {:?}", err);
+        self.notify(None, "s:ExecuteAutocmd", event)?;
+
+        info!("End {}", method);
+        Ok(Value::Null)
+    }
+
+    pub fn exit(&mut self, params: &Value) -> Result<()> {
+        info!("Begin {}", lsp::notification::Exit::METHOD);
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
+
+        let mut keys = self.get_additional_server_keys(&languageId);
+        keys.push(languageId);
+        for key in keys {
+            let result = self.notify(Some(&key), lsp::notification::Exit::METHOD, Value::Null);
+            if let Err(err) = result {
+                error!("Error: {:?}", err);
+            }
+            if let Err(err) = self.cleanup(&key) {
+                error!("Error: {:?}", err);
+            }
         }
         info!("End {}", lsp::notification::Exit::METHOD);
         Ok(())
@@ -2072,7 +4246,9 @@ impl State {
 
     pub fn languageClient_isAlive(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__IsAlive);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
         let is_alive = self.get(|state| Ok(state.writers.contains_key(&languageId)))?;
         info!("End {}", REQUEST__IsAlive);
         Ok(Value::Bool(is_alive))
@@ -2080,7 +4256,7 @@ impl State {
 
     pub fn languageClient_registerServerCommands(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__RegisterServerCommands);
-        let commands: HashMap<String, Vec<String>> = params.clone().to_lsp()?;
+        let commands: HashMap<String, ServerCommand> = params.clone().to_lsp()?;
         self.update(|state| {
             state.serverCommands.extend(commands);
             Ok(())
@@ -2094,6 +4270,42 @@ impl State {
         Ok(Value::Null)
     }
 
+    /// Check server command answers initialize request in current directory, see
+    /// LanguageClient#setup().
+    pub fn languageClient_verifyServerCommand(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__VerifyServerCommand);
+        let (command,): (Vec<String>,) = self.gather_args(&["command"], params)?;
+        let root: String = self.eval("getcwd()")?;
+
+        // A server that hangs must not block the client. It's killed on timeout.
+        let (ok, message) =
+            match cli::verify_server(&command, Path::new(&root), self.wait_output_timeout) {
+                Ok(name) => (true, name),
+                Err(err) => (false, format!("{}", err)),
+            };
+
+        info!("End {}", REQUEST__VerifyServerCommand);
+        Ok(json!({
+            "ok": ok,
+            "message": message,
+        }))
+    }
+
+    pub fn languageClient_registerUriHandlers(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__RegisterUriHandlers);
+        let handlers: HashMap<String, String> = params.clone().to_lsp()?;
+        self.update(|state| {
+            state.uriHandlers.extend(
+                handlers
+                    .into_iter()
+                    .map(|(scheme, function)| (scheme, UriHandler::Function(function))),
+            );
+            Ok(())
+        })?;
+        info!("End {}", REQUEST__RegisterUriHandlers);
+        Ok(Value::Null)
+    }
+
     pub fn languageClient_setLoggingLevel(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__SetLoggingLevel);
         let (loggingLevel,): (log::LevelFilter,) = self.gather_args(&["loggingLevel"], params)?;
@@ -2167,7 +4379,11 @@ impl State {
             let ret = self.languageClient_startServer(params);
             // This is triggered from autocmd, silent all errors.
             if let Err(err) = ret {
-                warn!("Failed to start language server automatically. {}", err);
+                match err.downcast_ref::<LCError>() {
+                    Some(LCError::NoServerCommand { .. })
+                    | Some(LCError::ServerAlreadyStarted { .. }) => {}
+                    _ => warn!("Failed to start language server automatically. {}", err),
+                }
             }
         }
 
@@ -2193,7 +4409,11 @@ impl State {
 
         let filename = filename.canonicalize();
 
-        if self.get(|state| Ok(state.writers.contains_key(&languageId)))? {
+        let start_instance = self.outsideRootPolicy == OutsideRootPolicy::Start
+            && self.find_server_key(&languageId, &filename).is_none();
+        let server_key = self.get_server_key(&languageId, &filename);
+        let server_running = self.writers.contains_key(&server_key);
+        if (server_running && !start_instance) || self.is_save_command_only(&languageId) {
             self.textDocument_didOpen(params)?;
 
             if let Some(diagnostics) = self.diagnostics.get(&filename).cloned() {
@@ -2206,7 +4426,11 @@ impl State {
                 let ret = self.languageClient_startServer(params);
                 // This is triggered from autocmd, silent all errors.
                 if let Err(err) = ret {
-                    warn!("Failed to start language server automatically. {}", err);
+                    match err.downcast_ref::<LCError>() {
+                        Some(LCError::NoServerCommand { .. })
+                        | Some(LCError::ServerAlreadyStarted { .. }) => {}
+                        _ => warn!("Failed to start language server automatically. {}", err),
+                    }
                 }
             }
         }
@@ -2260,6 +4484,9 @@ impl State {
             state.diagnostics.retain(|f, _| f != &filename);
             state.line_diagnostics.retain(|fl, _| fl.0 != filename);
             state.signs.retain(|f, _| f != &filename);
+            // A buffer of the same name loaded later has none of these placed.
+            state.signs_placed.retain(|f, _| f != &filename);
+            state.highlights_placed.retain(|f, _| f != &filename);
             Ok(())
         })?;
         self.textDocument_didClose(params)?;
@@ -2316,6 +4543,9 @@ impl State {
             );
             self.signs_placed.insert(filename.clone(), signs);
 
+            if !self.signs_defined {
+                self.define_signs()?;
+            }
             info!("Updating signs: {:?}", cmds);
             self.command(&cmds)?;
         }
@@ -2362,6 +4592,49 @@ impl State {
         Ok(())
     }
 
+    pub fn languageClient_handleCursorHold(&mut self, params: &Value) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__HandleCursorHold);
+        let (buftype, languageId, filename): (String, String, String) = self.gather_args(
+            &[VimVar::Buftype, VimVar::LanguageId, VimVar::Filename],
+            params,
+        )?;
+        if !buftype.is_empty() || languageId.is_empty() {
+            return Ok(());
+        }
+        let languageId = self.get_server_key(&languageId, &filename);
+
+        if self.update(|state| Ok(state.warmup_pending.remove(&languageId)))? {
+            self.warm_up(&languageId, &filename);
+        }
+
+        info!("End {}", NOTIFICATION__HandleCursorHold);
+        Ok(())
+    }
+
+    /// Issue requests whose results are discarded, so that the language server pays its
+    /// cold-start cost while user is idle rather than on the first user-triggered action.
+    fn warm_up(&mut self, languageId: &str, filename: &str) {
+        info!("Warming up language server: {}", languageId);
+        let params = json!({
+            "buftype": "",
+            "languageId": languageId,
+            "filename": filename,
+            "query": "",
+            "handle": false,
+        });
+
+        if self.has_capability(languageId, "workspaceSymbolProvider") {
+            if let Err(err) = self.workspace_symbol(&params) {
+                warn!("Failed to warm up workspace/symbol: {}", err);
+            }
+        }
+        if self.has_capability(languageId, "documentSymbolProvider") {
+            if let Err(err) = self.textDocument_documentSymbol(&params) {
+                warn!("Failed to warm up textDocument/documentSymbol: {}", err);
+            }
+        }
+    }
+
     pub fn languageClient_handleCompleteDone(&mut self, params: &Value) -> Result<()> {
         let (filename, completed_item, line, character): (
             String,
@@ -2412,46 +4685,37 @@ impl State {
         let params = match params {
             Value::Array(ref arr) => Value::Array(arr.clone()),
             _ => {
-                bail!("Expecting array params!");
+                return Err(LCError::InvalidParams {
+                    message: "expecting array params".into(),
+                }.into());
             }
         };
 
         let lines: Vec<String> = serde_json::from_value(params)?;
-        if lines.is_empty() {
-            err_msg("No selection!");
-        }
-
+        let invalid_selection = || LCError::InvalidParams {
+            message: format!("selection {:?}", lines),
+        };
         let location = lines
             .get(0)
-            .ok_or_else(|| format_err!("Failed to get line! lines: {:?}", lines))?
+            .ok_or_else(invalid_selection)?
             .split('\t')
             .next()
-            .ok_or_else(|| format_err!("Failed to parse: {:?}", lines))?;
+            .ok_or_else(invalid_selection)?;
         let mut tokens: Vec<_> = location.split_terminator(':').collect();
         tokens.reverse();
         let filename: String = if tokens.len() > 2 {
-            let relpath = tokens
-                .pop()
-                .ok_or_else(|| format_err!("Failed to get file path! tokens: {:?}", tokens))?
-                .to_owned();
+            let relpath = tokens.pop().ok_or_else(invalid_selection)?.to_owned();
             let cwd: String = self.eval("getcwd()")?;
             Path::new(&cwd).join(relpath).to_string_lossy().into_owned()
         } else {
             self.eval(VimVar::Filename)?
         };
-        let line = tokens
-            .pop()
-            .ok_or_else(|| format_err!("Failed to get line! tokens: {:?}", tokens))?
-            .to_int()?
-            - 1;
-        let character = tokens
-            .pop()
-            .ok_or_else(|| format_err!("Failed to get character! tokens: {:?}", tokens))?
-            .to_int()?
-            - 1;
-
-        self.edit(&None, &filename)?;
-        self.cursor(line + 1, character + 1)?;
+        let line = tokens.pop().ok_or_else(invalid_selection)?.to_int()? - 1;
+        let character = tokens.pop().ok_or_else(invalid_selection)?.to_int()? - 1;
+
+        if self.edit(&None, &filename)? {
+            self.cursor(line + 1, character + 1)?;
+        }
 
         info!("End {}", NOTIFICATION__FZFSinkLocation);
         Ok(())
@@ -2461,27 +4725,39 @@ impl State {
         info!("Begin {}", NOTIFICATION__FZFSinkCommand);
         let (selection,): (String,) = self.gather_args(&["selection"], params)?;
         let tokens: Vec<&str> = selection.splitn(2, ": ").collect();
-        let command = tokens
-            .get(0)
-            .cloned()
-            .ok_or_else(|| format_err!("Failed to get command! tokens: {:?}", tokens))?;
-        let title = tokens
-            .get(1)
-            .cloned()
-            .ok_or_else(|| format_err!("Failed to get title! tokens: {:?}", tokens))?;
+        let invalid_selection = || LCError::InvalidParams {
+            message: format!("selection {:?}", selection),
+        };
+        let command = tokens.get(0).cloned().ok_or_else(invalid_selection)?;
+        let title = tokens.get(1).cloned().ok_or_else(invalid_selection)?;
         let entry = self.get(|state| {
             let commands = &state.stashed_codeAction_commands;
 
             commands
                 .iter()
-                .find(|e| e.command == command && e.title == title)
+                .find(|(e, _)| e.command == command && e.title == title)
                 .cloned()
                 .ok_or_else(|| {
-                    format_err!("No stashed command found! stashed commands: {:?}", commands)
+                    LCError::NotFound {
+                        what: format!("code action {}: {}", command, title),
+                    }.into()
                 })
         })?;
 
-        if self.try_handle_command_by_client(&entry)? {
+        self.execute_codeAction(&entry.0, entry.1)?;
+
+        info!("End {}", NOTIFICATION__FZFSinkCommand);
+        Ok(())
+    }
+
+    fn execute_codeAction(&mut self, entry: &Command, kind: Option<String>) -> Result<()> {
+        self.update(|state| {
+            state.last_codeAction = Some((entry.clone(), kind));
+            Ok(())
+        })?;
+
+        self.save_all()?;
+        if self.try_handle_command_by_client(entry)? {
             return Ok(());
         }
 
@@ -2495,10 +4771,46 @@ impl State {
             Ok(())
         })?;
 
-        info!("End {}", NOTIFICATION__FZFSinkCommand);
         Ok(())
     }
 
+    /// Re-query code actions at cursor and execute the one matching last executed code action.
+    pub fn languageClient_repeatLastCodeAction(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__RepeatLastCodeAction);
+        let (last, last_kind) = self
+            .get(|state| Ok(state.last_codeAction.clone()))?
+            .ok_or_else(|| LCError::NotFound {
+                what: "code action executed before".into(),
+            })?;
+
+        let result = self.textDocument_codeAction(&params.combine(&json!({
+            "handle": false,
+        })))?;
+        if result == Value::Null {
+            return Ok(Value::Null);
+        }
+
+        // Same command might do something entirely different with another title or kind, e.g.,
+        // a generic "apply fix" command, so only an exact match is repeated.
+        let entry = get_code_action_commands(&result)?
+            .into_iter()
+            .find(|(cmd, kind)| {
+                cmd.command == last.command && cmd.title == last.title && *kind == last_kind
+            });
+        let (entry, kind) = match entry {
+            Some(entry) => entry,
+            None => {
+                self.echowarn(format!("No matching action for '{}' here.", last.title))?;
+                return Ok(Value::Null);
+            }
+        };
+
+        self.execute_codeAction(&entry, kind)?;
+
+        info!("End {}", REQUEST__RepeatLastCodeAction);
+        Ok(serde_json::to_value(entry)?)
+    }
+
     pub fn NCM_refresh(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__NCMRefresh);
         let params: NCMRefreshParams = serde_json::from_value(rpc::to_value(params.clone())?)?;
@@ -2609,19 +4921,18 @@ impl State {
             state
                 .diagnostics
                 .get(&filename)
-                .ok_or_else(|| format_err!("No diagnostics found: filename: {}", filename,))?
+                .ok_or_else(|| LCError::NotFound {
+                    what: format!("diagnostics of {}", filename),
+                })?
                 .iter()
                 .find(|d| {
                     (line, character) >= (d.range.start.line, d.range.start.character)
                         && (line, character) < (d.range.end.line, d.range.end.character)
                 }).cloned()
                 .ok_or_else(|| {
-                    format_err!(
-                        "No diagnostics found: filename: {}, line: {}, character: {}",
-                        filename,
-                        line,
-                        character
-                    )
+                    LCError::NotFound {
+                        what: format!("diagnostics at {}:{}:{}", filename, line, character),
+                    }.into()
                 })
         })?;
         let message: Vec<_> = diag.message.lines().collect();
@@ -2664,16 +4975,13 @@ impl State {
         if !buftype.is_empty() || languageId.is_empty() {
             return Ok(Value::Null);
         }
+        let languageId =
+            self.route_server_key(&languageId, &filename, REQUEST__RustImplementations)?;
 
         let result = self.call(
             Some(&languageId),
             REQUEST__RustImplementations,
-            TextDocumentPositionParams {
-                text_document: TextDocumentIdentifier {
-                    uri: filename.to_url()?,
-                },
-                position: Position { line, character },
-            },
+            self.text_document_position(&filename, line, character)?,
         )?;
 
         if !handle {
@@ -2757,6 +5065,136 @@ impl State {
         Ok(())
     }
 
+    pub fn progress(&mut self, languageId: &str, params: &Value) -> Result<()> {
+        info!("Begin {}", NOTIFICATION__Progress);
+        let token = params.get("token").cloned().unwrap_or_default();
+        let value = params.get("value").cloned().unwrap_or_default();
+        let key = token.to_string();
+        let message = value["message"].as_str().map(|s| s.to_owned());
+        let percentage = value["percentage"].as_u64();
+        let cancellable = value["cancellable"].as_bool();
+
+        self.update(|state| {
+            let progress = state
+                .progress
+                .entry(languageId.to_owned())
+                .or_insert_with(HashMap::new);
+            match value["kind"].as_str() {
+                Some("begin") => {
+                    progress.insert(
+                        key,
+                        WorkDoneProgress {
+                            token,
+                            title: value["title"].as_str().unwrap_or("Busy").to_owned(),
+                            message,
+                            percentage,
+                            cancellable: cancellable.unwrap_or(false),
+                        },
+                    );
+                }
+                Some("report") => {
+                    if let Some(progress) = progress.get_mut(&key) {
+                        if message.is_some() {
+                            progress.message = message;
+                        }
+                        if percentage.is_some() {
+                            progress.percentage = percentage;
+                        }
+                        if let Some(cancellable) = cancellable {
+                            progress.cancellable = cancellable;
+                        }
+                    }
+                }
+                Some("end") => {
+                    progress.remove(&key);
+                }
+                _ => warn!("Unknown progress: {:?}", value),
+            }
+            Ok(())
+        })?;
+
+        self.update_progress_status()?;
+        info!("End {}", NOTIFICATION__Progress);
+        Ok(())
+    }
+
+    fn update_progress_status(&mut self) -> Result<()> {
+        let mut progresses: Vec<WorkDoneProgress> = self.get(|state| {
+            Ok(state
+                .progress
+                .values()
+                .flat_map(|progress| progress.values().cloned())
+                .collect())
+        })?;
+        progresses.sort_by(|a, b| a.title.cmp(&b.title));
+
+        let mut buf = "LS: ".to_owned();
+        if let Some(progress) = progresses.first() {
+            buf += &progress.title;
+            if let Some(ref message) = progress.message {
+                buf += &format!(" ({})", message);
+            }
+            if let Some(percentage) = progress.percentage {
+                buf += &format!(" ({}% done)", percentage);
+            }
+            if progresses.len() > 1 {
+                buf += &format!(" (+{} more)", progresses.len() - 1);
+            }
+            if progresses.iter().any(|progress| progress.cancellable) {
+                buf += " [cancel: LanguageClient#cancelProgress()]";
+            }
+        } else {
+            buf += "Idle";
+        }
+
+        self.command(vec![
+            format!(
+                "let {}={}",
+                VIM__ServerStatus,
+                if progresses.is_empty() { 0 } else { 1 }
+            ),
+            format!(
+                "let {}='{}'",
+                VIM__ServerStatusMessage,
+                &escape_single_quote(buf)
+            ),
+        ])?;
+        Ok(())
+    }
+
+    pub fn languageClient_cancelProgress(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__CancelProgress);
+        let (token,): (Value,) = self.gather_args(&[("token", "v:null")], params)?;
+
+        let cancellable: Vec<(String, Value)> = self.get(|state| {
+            Ok(state
+                .progress
+                .iter()
+                .flat_map(|(languageId, progress)| {
+                    progress
+                        .values()
+                        .filter(|progress| progress.cancellable)
+                        .map(move |progress| (languageId.clone(), progress.token.clone()))
+                })
+                .filter(|(_, t)| token.is_null() || *t == token)
+                .collect())
+        })?;
+
+        if cancellable.is_empty() {
+            self.echowarn("No cancellable progress.")?;
+        }
+        for (languageId, token) in &cancellable {
+            self.notify(
+                Some(languageId),
+                NOTIFICATION__WorkDoneProgressCancel,
+                json!({ "token": token }),
+            )?;
+        }
+
+        info!("End {}", REQUEST__CancelProgress);
+        Ok(json!(cancellable.len()))
+    }
+
     pub fn languageClient_startServer(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__StartServer);
         let (cmdargs,): (Vec<String>,) = self.gather_args(&[("cmdargs", "[]")], params)?;
@@ -2771,34 +5209,82 @@ impl State {
             return Ok(Value::Null);
         }
 
-        if self.get(|state| Ok(state.writers.contains_key(&languageId)))? {
-            bail!(
-                "Language client has already started for language {}.",
-                &languageId
-            );
-        }
+        // Additional servers are started along with the main server of the language.
+        let (serverName,): (Option<String>,) =
+            self.gather_args(&[("serverName", "v:null")], &params)?;
+
+        if serverName.is_none() {
+            if let Some(key) = self.find_server_key(&languageId, &filename) {
+                if self.get(|state| Ok(state.writers.contains_key(&key)))? {
+                    return Err(LCError::ServerAlreadyStarted {
+                        languageId: languageId.clone(),
+                    }.into());
+                }
+            }
 
-        self.sync_settings()?;
+            self.sync_settings()?;
+        }
 
         let command = self.get(|state| {
-            state
-                .serverCommands
-                .get(&languageId)
-                .cloned()
-                .ok_or_else(|| {
-                    format_err!(
-                        "No language server command found for file type: {}.",
-                        &languageId
-                    )
-                })
+            let command = match serverName {
+                Some(ref name) => state
+                    .additionalServerCommands
+                    .get(&languageId)
+                    .and_then(|commands| commands.get(name)),
+                None => state.serverCommands.get(&languageId),
+            };
+            command.cloned().ok_or_else(|| {
+                LCError::NoServerCommand {
+                    languageId: languageId.clone(),
+                }.into()
+            })
         })?;
 
+        let (rootPath,): (Option<String>,) = self.gather_args(&[("rootPath", "v:null")], &params)?;
+        let root = if let Some(r) = rootPath {
+            r
+        } else {
+            let rootMarkers = self.get(|state| Ok(state.rootMarkers.clone()))?;
+            let root = get_rootPath(Path::new(&filename), &languageId, &rootMarkers)?
+                .to_string_lossy()
+                .into_owned();
+            self.echomsg_ellipsis(format!("LanguageClient project root: {}", root))?;
+            root
+        };
+        info!("Project root: {}", root);
+
+        let key = if let Some(ref name) = serverName {
+            format!("{}#{}@{}", languageId, name, root)
+        } else if self.writers.contains_key(&languageId) {
+            format!("{}@{}", languageId, root)
+        } else {
+            languageId.clone()
+        };
+        if serverName.is_some() && self.writers.contains_key(&key) {
+            return Err(LCError::ServerAlreadyStarted { languageId: key }.into());
+        }
+        self.update(|state| Ok(state.roots.insert(key.clone(), root.clone())))?;
+        let server_params = params.combine(&json!({
+            VimVar::LanguageId.to_key(): key,
+            "rootPath": root,
+        }));
+
+        let placeholders = hashmap!{
+            "rootPath" => root.clone(),
+            "file" => filename.clone(),
+            "tmpdir" => env::temp_dir().to_string_lossy().into_owned(),
+        };
+        let shell = command.shell();
+        let command = expand_command_placeholders(command.command(), &placeholders, shell);
+
         let (child_id, reader, writer): (_, Box<dyn SyncRead>, Box<dyn SyncWrite>) =
             if command.get(0).map(|c| c.starts_with("tcp://")) == Some(true) {
                 let addr = command
                     .get(0)
                     .map(|s| s.replace("tcp://", ""))
-                    .ok_or_else(|| err_msg("Server command can't be empty!"))?;
+                    .ok_or_else(|| LCError::InvalidCommand {
+                        message: "empty server command".into(),
+                    })?;
                 let stream = TcpStream::connect(addr)?;
                 let reader = Box::new(BufReader::new(stream.try_clone()?));
                 let writer = Box::new(BufWriter::new(stream));
@@ -2825,16 +5311,25 @@ impl State {
                     None => Stdio::null(),
                 };
 
-                let process = std::process::Command::new(
-                    command.get(0).ok_or_else(|| err_msg("Empty command!"))?,
-                ).args(&command[1..])
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(stderr)
-                .spawn()
-                .with_context(|err| {
-                    format!("Failed to start language server ({:?}): {}", command, err)
-                })?;
+                let mut cmd = if shell {
+                    shell_command(&command)
+                } else {
+                    let mut cmd = std::process::Command::new(
+                        command.get(0).ok_or_else(|| LCError::InvalidCommand {
+                            message: "empty server command".into(),
+                        })?,
+                    );
+                    cmd.args(&command[1..]);
+                    cmd
+                };
+                let process = cmd
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(stderr)
+                    .spawn()
+                    .with_context(|err| {
+                        format!("Failed to start language server ({:?}): {}", command, err)
+                    })?;
 
                 let child_id = Some(process.id());
                 let reader = Box::new(BufReader::new(
@@ -2851,13 +5346,13 @@ impl State {
             };
 
         self.update(|state| {
-            child_id.map(|id| state.child_ids.insert(languageId.clone(), id));
-            state.writers.insert(languageId.clone(), writer);
+            child_id.map(|id| state.child_ids.insert(key.clone(), id));
+            state.writers.insert(key.clone(), writer);
             Ok(())
         })?;
 
-        let thread_name = format!("reader-{}", languageId);
-        let languageId_clone = languageId.clone();
+        let thread_name = format!("reader-{}", key);
+        let languageId_clone = key.clone();
         let tx = self.tx.clone();
         std::thread::Builder::new()
             .name(thread_name.clone())
@@ -2880,26 +5375,59 @@ impl State {
 
         info!("End {}", REQUEST__StartServer);
 
-        if self.get(|state| Ok(state.writers.len()))? == 1 {
-            self.define_signs()?;
-        }
-
-        self.initialize(&params)?;
-        self.initialized(&params)?;
+        self.initialize(&server_params)?;
+        self.initialized(&server_params)?;
+        self.update_server_instances()?;
 
-        let root = self.roots.get(&languageId).cloned().unwrap_or_default();
-        match self.get_workspace_settings(&root) {
-            Ok(Value::Null) => (),
-            Ok(settings) => self.workspace_didChangeConfiguration(&json!({
-                VimVar::LanguageId.to_key(): languageId,
+        let vscode_settings = self
+            .get_vscode_settings(&languageId, &root)
+            .unwrap_or_else(|err| {
+                warn!("Failed to import VSCode settings: {}", err);
+                Value::Null
+            });
+        let settings = match self.get_workspace_settings(&root) {
+            Ok(settings) => vscode_settings.combine(&settings),
+            Err(err) => {
+                warn!("Failed to get workspace settings: {}", err);
+                self.echowarn(format!("LanguageClient: ignoring workspace settings. {}", err))?;
+                vscode_settings
+            }
+        };
+        if !settings.is_null() {
+            self.workspace_didChangeConfiguration(&json!({
+                VimVar::LanguageId.to_key(): key,
                 "settings": settings,
-            }))?,
-            Err(err) => warn!("Failed to get workspace settings: {}", err),
+            }))?;
+        }
+
+        // Documents are synced to additional servers on demand, when routed to them.
+        if serverName.is_some() {
+            return Ok(Value::Null);
         }
 
         self.textDocument_didOpen(&params)?;
         self.textDocument_didChange(&params)?;
 
+        if self.get(|state| Ok(state.warmUp.get(&languageId) == Some(&true)))? {
+            self.update(|state| Ok(state.warmup_pending.insert(key.clone())))?;
+        }
+
+        let names: Vec<String> = self
+            .additionalServerCommands
+            .get(&languageId)
+            .map(|commands| commands.keys().cloned().collect())
+            .unwrap_or_default();
+        for name in names {
+            let result = self.languageClient_startServer(&params.combine(&json!({
+                "serverName": name,
+                "rootPath": root,
+            })));
+            if let Err(err) = result {
+                warn!("Failed to start additional language server {}: {}", name, err);
+                self.echowarn(format!("Failed to start language server {}: {}", name, err))?;
+            }
+        }
+
         self.notify(None, "s:ExecuteAutocmd", "LanguageClientStarted")?;
         Ok(Value::Null)
     }
@@ -2984,7 +5512,9 @@ impl State {
 
     pub fn java_classFileContents(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__ClassFileContents);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
 
         let content: String = self.call(
             Some(languageId.as_str()),
@@ -2998,7 +5528,9 @@ impl State {
 
     pub fn debug_info(&mut self, params: &Value) -> Result<Value> {
         info!("Begin {}", REQUEST__DebugInfo);
-        let (languageId,): (String,) = self.gather_args(&[VimVar::LanguageId], params)?;
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let languageId = self.get_server_key(&languageId, &filename);
         let mut msg = String::new();
         msg += &format!(
             "Project root: {}\n",
@@ -3021,4 +5553,411 @@ impl State {
         info!("End {}", REQUEST__DebugInfo);
         Ok(json!(msg))
     }
+
+    /// Tags for 'tagfunc', from definition of symbol under cursor or from workspace symbols.
+    pub fn languageClient_tags(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__Tags);
+        let (pattern, flags): (String, String) =
+            self.gather_args(&[("pattern", "''"), ("flags", "''")], params)?;
+
+        let tags = if flags.contains('c') {
+            let result = self.find_locations(&params.combine(&json!({
+                "method": lsp::request::GotoDefinition::METHOD,
+                "handle": false,
+            })))?;
+            let response: Option<GotoDefinitionResponse> = result.to_lsp()?;
+            let locations = match response {
+                None => vec![],
+                Some(GotoDefinitionResponse::Scalar(loc)) => vec![loc],
+                Some(GotoDefinitionResponse::Array(arr)) => arr,
+            };
+            locations
+                .iter()
+                .map(|loc| location_to_tag(&pattern, loc, None))
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let result = self.workspace_symbol(&params.combine(&json!({
+                "query": pattern,
+                "handle": false,
+            })))?;
+            let symbols: Option<Vec<SymbolInformation>> = result.to_lsp()?;
+            let re = if flags.contains('r') {
+                regex::Regex::new(&pattern).ok()
+            } else {
+                None
+            };
+            symbols
+                .unwrap_or_default()
+                .iter()
+                .filter(|symbol| match re {
+                    Some(ref re) => re.is_match(&symbol.name),
+                    // Insert mode completion.
+                    None if flags.contains('i') => symbol.name.starts_with(&pattern),
+                    None => symbol.name == pattern,
+                }).map(|symbol| {
+                    let kind = format!("{:?}", symbol.kind).to_lowercase();
+                    location_to_tag(&symbol.name, &symbol.location, Some(&kind))
+                }).collect::<Result<Vec<_>>>()?
+        };
+
+        info!("End {}", REQUEST__Tags);
+        Ok(json!(tags))
+    }
+
+    pub fn languageClient_compareCapabilities(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__CompareCapabilities);
+        let (languageId, filename): (String, String) =
+            self.gather_args(&[VimVar::LanguageId, VimVar::Filename], params)?;
+        let candidates = self.get_server_candidates(&languageId, &filename);
+        if candidates.is_empty() {
+            return Err(LCError::ServerNotRunning { languageId }.into());
+        }
+        let default = self.get_server_key(&languageId, &filename);
+        let project_root = self.roots.get(&default).cloned().unwrap_or_default();
+
+        let mut lines = vec!["Servers:".to_owned()];
+        for (i, key) in candidates.iter().enumerate() {
+            lines.push(format!(
+                "  [{}] {} ({})",
+                i + 1,
+                get_server_languageId(key),
+                self.roots.get(key).cloned().unwrap_or_default()
+            ));
+        }
+        lines.push("".to_owned());
+        lines.push("* preferred, + provided, - not provided".to_owned());
+        lines.push("".to_owned());
+
+        let header: Vec<String> = (1..=candidates.len()).map(|i| format!("[{}]", i)).collect();
+        lines.push(format!("{:40} {}", "Method", header.join(" ")));
+        let mut preferred = serde_json::Map::new();
+        for &(method, capability) in METHOD_CAPABILITIES {
+            let key = self.get_preferred_server_key(&project_root, &candidates, method);
+            let cells: Vec<String> = candidates
+                .iter()
+                .map(|candidate| {
+                    let mark = if Some(candidate) == key.as_ref() {
+                        "*"
+                    } else if self.has_capability(candidate, capability) {
+                        "+"
+                    } else {
+                        "-"
+                    };
+                    format!("{:3}", mark)
+                }).collect();
+            let pinned = if self.pinnedServers.contains_key(method) {
+                " (pinned)"
+            } else {
+                ""
+            };
+            lines.push(format!("{:40} {}{}", method, cells.join(" "), pinned));
+            preferred.insert(
+                method.to_owned(),
+                json!(key.and_then(|key| self.roots.get(&key).cloned())),
+            );
+        }
+
+        self.preview(&lines)?;
+        info!("End {}", REQUEST__CompareCapabilities);
+        Ok(Value::Object(preferred))
+    }
+
+    /// Proxy a request from vim to language server, serving repeats for the same document
+    /// version, method and position from cache.
+    pub fn cached_proxy(
+        &mut self,
+        kind: CacheKind,
+        languageId: &str,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value> {
+        let (filename,): (String,) = self.gather_args(&[VimVar::Filename], params)?;
+        // Not whole params, which carry buffer text. Document version covers that.
+        let position: Vec<_> = ["position", "range", "line", "character"]
+            .iter()
+            .map(|key| params.get(*key).cloned().unwrap_or_default())
+            .collect();
+        let cache_key =
+            self.document_cache_key(&filename, &format!("{}:{}", method, json!(position)));
+        if let Some(result) = self.get_cached(kind, &cache_key) {
+            return Ok(result);
+        }
+
+        let result: Value = self.call(Some(languageId), method, params)?;
+        self.set_cached(kind, cache_key, &result);
+        Ok(result)
+    }
+
+    /// Approximate memory usage of the larger stores, by serialized size.
+    fn memory_usage(&self) -> Result<Value> {
+        let line_diagnostics: Vec<_> = self.line_diagnostics.values().collect();
+        let mut usage = serde_json::Map::new();
+        // Serializing all buffers is too costly to do every second. Text is the bulk of it.
+        usage.insert(
+            "text_documents".into(),
+            json!({
+                "entries": self.text_documents.len(),
+                "bytes": self
+                    .text_documents
+                    .values()
+                    .map(|document| document.text.len())
+                    .sum::<usize>(),
+            }),
+        );
+        usage.insert(
+            "diagnostics".into(),
+            get_store_usage(self.diagnostics.len(), &self.diagnostics)?,
+        );
+        usage.insert(
+            "line_diagnostics".into(),
+            get_store_usage(line_diagnostics.len(), &line_diagnostics)?,
+        );
+        usage.insert(
+            "signs".into(),
+            get_store_usage(self.signs.len(), &self.signs)?,
+        );
+        usage.insert(
+            "highlights".into(),
+            get_store_usage(self.highlights.len(), &self.highlights)?,
+        );
+        usage.insert(
+            "pending_outputs".into(),
+            get_store_usage(self.pending_outputs.len(), &self.pending_outputs)?,
+        );
+        for (kind, entries) in &self.response_cache {
+            usage.insert(
+                format!("cache.{:?}", kind),
+                get_store_usage(entries.len(), entries)?,
+            );
+        }
+        Ok(Value::Object(usage))
+    }
+
+    /// Snapshot of client metrics, as served at g:LanguageClient_metricsAddress.
+    fn metrics(&self) -> Result<Value> {
+        let mut servers = serde_json::Map::new();
+        for (languageId, root) in &self.roots {
+            if !self.writers.contains_key(languageId) {
+                continue;
+            }
+            let latency = self.latencies.get(languageId).cloned().unwrap_or_default();
+            servers.insert(
+                languageId.clone(),
+                json!({
+                    "root": root,
+                    "pid": self.child_ids.get(languageId),
+                    "requests": latency.requests,
+                    "latency_last_ms": latency.last_ms,
+                    "latency_average_ms": latency.average_ms(),
+                }),
+            );
+        }
+
+        let mut diagnostics = hashmap!{
+            "error" => 0,
+            "warning" => 0,
+            "information" => 0,
+            "hint" => 0,
+        };
+        for diagnostic in self.diagnostics.values().flat_map(|d| d.iter()) {
+            let severity = match diagnostic.severity {
+                Some(DiagnosticSeverity::Warning) => "warning",
+                Some(DiagnosticSeverity::Information) => "information",
+                Some(DiagnosticSeverity::Hint) => "hint",
+                _ => "error",
+            };
+            *diagnostics.entry(severity).or_insert(0) += 1;
+        }
+
+        let memory = self.memory_usage()?;
+        let memory_bytes: u64 = memory
+            .as_object()
+            .map(|stores| stores.values().filter_map(|s| s["bytes"].as_u64()).sum())
+            .unwrap_or_default();
+
+        Ok(json!({
+            "pid": std::process::id(),
+            "servers": servers,
+            "documents": self.text_documents.len(),
+            "diagnostics": diagnostics,
+            "memory_bytes": memory_bytes,
+        }))
+    }
+
+    /// Refresh snapshot served by metrics server, at most once a second.
+    pub fn update_metrics(&mut self) -> Result<()> {
+        let throttle = Duration::from_secs(1);
+        if self.metricsAddress.is_none() || self.last_metrics_update.elapsed() < throttle {
+            return Ok(());
+        }
+        self.last_metrics_update = Instant::now();
+
+        let metrics = self.metrics()?;
+        *self
+            .metrics
+            .lock()
+            .map_err(|_| err_msg("Failed to lock metrics"))? = metrics;
+        Ok(())
+    }
+
+    /// Serve metrics as JSON on a local address, for status bars or dashboards outside vim. Any
+    /// request, HTTP or not, is answered with an HTTP response carrying the latest snapshot.
+    fn start_metrics_server(&mut self, address: &str) -> Result<()> {
+        // Metrics reveal project paths, so they're not served beyond this machine.
+        let addrs: Vec<_> = address
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect())
+            .unwrap_or_default();
+        if addrs.is_empty() || addrs.iter().any(|addr| !addr.ip().is_loopback()) {
+            return Err(LCError::InvalidOption {
+                option: "metricsAddress".into(),
+                value: address.into(),
+            }.into());
+        }
+
+        self.stop_metrics_server();
+        info!("Starting metrics server at {}", address);
+        let listener = TcpListener::bind(address)
+            .with_context(|err| format!("Failed to listen on {}: {}", address, err))?;
+        let metrics = Arc::clone(&self.metrics);
+        let stop = Arc::new(AtomicBool::new(false));
+        self.metrics_server = Some((address.to_owned(), Arc::clone(&stop)));
+        thread::Builder::new()
+            .name("metrics".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        info!("Metrics server stopped");
+                        break;
+                    }
+                    let mut stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            warn!("Failed to accept metrics connection: {:?}", err);
+                            continue;
+                        }
+                    };
+                    // Request itself is irrelevant. Consume what's readily available.
+                    let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+                    let mut request = [0; 1024];
+                    let _ = stream.read(&mut request);
+
+                    let body = metrics.lock().map(|m| m.to_string()).unwrap_or_default();
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Stop metrics server, if any. Its thread is blocked waiting for connections, so it's woken
+    /// up by one to notice.
+    fn stop_metrics_server(&mut self) {
+        if let Some((address, stop)) = self.metrics_server.take() {
+            info!("Stopping metrics server at {}", address);
+            stop.store(true, Ordering::SeqCst);
+            let _ = TcpStream::connect(&address);
+        }
+    }
+
+    pub fn languageClient_metrics(&mut self, _params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__Metrics);
+        let metrics = self.metrics()?;
+        info!("End {}", REQUEST__Metrics);
+        Ok(metrics)
+    }
+
+    pub fn languageClient_memoryUsage(&mut self, params: &Value) -> Result<Value> {
+        info!("Begin {}", REQUEST__MemoryUsage);
+        let (handle,): (bool,) = self.gather_args(&[VimVar::Handle], params)?;
+
+        let usage = self.memory_usage()?;
+
+        if handle {
+            let mut msg = String::new();
+            if let Value::Object(ref map) = usage {
+                let mut stores: Vec<_> = map.iter().collect();
+                stores.sort_by_key(|&(name, _)| name.clone());
+                for (name, store) in stores {
+                    msg += &format!(
+                        "{}: {} entries, {} bytes\n",
+                        name, store["entries"], store["bytes"]
+                    );
+                }
+            }
+            self.echo(&msg)?;
+        }
+
+        info!("End {}", REQUEST__MemoryUsage);
+        Ok(usage)
+    }
+
+    /// Drop state left behind by closed documents and stopped servers, and evict expired cache
+    /// entries. Triggered periodically by the gc thread.
+    pub fn languageClient_garbageCollect(&mut self, _params: &Value) -> Result<()> {
+        if self.gcInterval == Duration::default() || self.last_gc.elapsed() < self.gcInterval {
+            return Ok(());
+        }
+
+        info!("Begin {}", NOTIFICATION__GarbageCollect);
+        let diagnostics_removed = self.update(|state| {
+            state.last_gc = Instant::now();
+
+            let cacheTTL = state.cacheTTL.clone();
+            for (kind, entries) in &mut state.response_cache {
+                let ttl = cacheTTL.get(kind).cloned().unwrap_or_default();
+                entries.retain(|_, entry| entry.created.elapsed() < ttl);
+            }
+            state.response_cache.retain(|_, entries| !entries.is_empty());
+
+            // Diagnostics of files outside of all project roots, e.g., left over by a stopped
+            // server or published for files out of root by a running one, are dropped. Files
+            // still open or checked by save commands, which have no project root, are kept.
+            let roots: Vec<String> = state.roots.values().cloned().collect();
+            let diagnostics_len = state.diagnostics.len();
+            let text_documents = &state.text_documents;
+            let external_diagnostics = &state.external_diagnostics;
+            state.diagnostics.retain(|f, _| {
+                roots.iter().any(|root| f.starts_with(root))
+                    || text_documents.contains_key(f)
+                    || external_diagnostics.contains_key(f)
+            });
+            let diagnostics_removed = state.diagnostics.len() != diagnostics_len;
+
+            let text_documents = &state.text_documents;
+            let diagnostics = &state.diagnostics;
+            let is_live = |f: &String| text_documents.contains_key(f) || diagnostics.contains_key(f);
+            state
+                .text_documents_metadata
+                .retain(|f, _| text_documents.contains_key(f));
+            state
+                .symbol_index
+                .retain(|f, _| text_documents.contains_key(f));
+            state
+                .symbol_index_stale
+                .retain(|f| text_documents.contains_key(f));
+            state.line_diagnostics.retain(|fl, _| is_live(&fl.0));
+            state.signs.retain(|f, _| is_live(f));
+            state.signs_placed.retain(|f, _| is_live(f));
+            state.highlights.retain(|f, _| is_live(f));
+            state.highlights_placed.retain(|f, _| is_live(f));
+
+            // Nobody waits for these outputs anymore, i.e., requests that timed out.
+            state.pending_outputs.clear();
+
+            Ok(diagnostics_removed)
+        })?;
+
+        if diagnostics_removed {
+            self.update_quickfixlist()?;
+        }
+
+        info!("End {}", NOTIFICATION__GarbageCollect);
+        Ok(())
+    }
 }
@@ -1,18 +1,20 @@
 #![allow(non_snake_case, non_upper_case_globals, unknown_lints)]
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::Into;
 use std::env;
 use std::fmt::Debug;
 use std::fs::{read_to_string, File};
 use std::io::prelude::*;
 use std::io::{BufRead, BufReader, BufWriter};
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::process::{ChildStdin, ChildStdout, Stdio};
 use std::str::FromStr;
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -60,6 +62,7 @@ use notify::Watcher;
 extern crate structopt;
 use structopt::StructOpt;
 
+mod cli;
 mod types;
 use crate::types::*;
 mod utils;
@@ -70,12 +73,18 @@ mod rpchandler;
 mod vim;
 
 #[derive(Debug, StructOpt)]
-struct Arguments {}
+struct Arguments {
+    #[structopt(subcommand)]
+    subcommand: Option<cli::Subcommand>,
+}
 
 fn main() -> Result<()> {
     let version = format!("{} {}", env!("CARGO_PKG_VERSION"), env!("GIT_HASH"));
     let args = Arguments::clap().version(version.as_str());
-    let _ = args.get_matches();
+    let args = Arguments::from_clap(&args.get_matches());
+    if let Some(subcommand) = args.subcommand {
+        return cli::run(subcommand);
+    }
 
     let mut state = State::new()?;
 
@@ -91,5 +100,18 @@ fn main() -> Result<()> {
             }
         })?;
 
+    let tx = state.tx.clone();
+    thread::Builder::new().name("gc".into()).spawn(move || loop {
+        thread::sleep(Duration::from_secs(10));
+        let notification = rpc::Notification {
+            jsonrpc: None,
+            method: NOTIFICATION__GarbageCollect.into(),
+            params: None,
+        };
+        if tx.send(Message::Notification(None, notification)).is_err() {
+            break;
+        }
+    })?;
+
     state.loop_message()
 }
@@ -15,7 +15,11 @@ impl State {
                 .user_handlers
                 .get(&method_call.method)
                 .cloned()
-                .ok_or_else(|| err_msg("No user handler"))
+                .ok_or_else(|| {
+                    LCError::NotFound {
+                        what: "user handler".into(),
+                    }.into()
+                })
         });
         if let Ok(user_handler) = user_handler {
             return self.call(None, &user_handler, params);
@@ -28,6 +32,14 @@ impl State {
             lsp::request::UnregisterCapability::METHOD => {
                 self.client_unregisterCapability(languageId.unwrap_or_default(), &params)
             }
+            REQUEST__CodeLensRefresh
+            | REQUEST__SemanticTokensRefresh
+            | REQUEST__InlayHintRefresh
+            | REQUEST__DiagnosticRefresh => {
+                self.workspace_refresh(languageId.unwrap_or_default(), &method_call.method)
+            }
+            // Progress is tracked from $/progress begin, nothing to prepare.
+            REQUEST__WorkDoneProgressCreate => Ok(Value::Null),
             lsp::request::HoverRequest::METHOD => self.textDocument_hover(&params),
             REQUEST__FindLocations => self.find_locations(&params),
             lsp::request::Rename::METHOD => self.textDocument_rename(&params),
@@ -62,21 +74,41 @@ impl State {
             REQUEST__OmniComplete => self.languageClient_omniComplete(&params),
             REQUEST__ClassFileContents => self.java_classFileContents(&params),
             REQUEST__DebugInfo => self.debug_info(&params),
+            REQUEST__MemoryUsage => self.languageClient_memoryUsage(&params),
+            REQUEST__PreviewFormatting => self.languageClient_previewFormatting(&params),
+            REQUEST__FilterReferences => self.languageClient_filterReferences(&params),
+            REQUEST__RepeatLastCodeAction => self.languageClient_repeatLastCodeAction(&params),
+            REQUEST__CancelProgress => self.languageClient_cancelProgress(&params),
+            REQUEST__CompareCapabilities => self.languageClient_compareCapabilities(&params),
+            REQUEST__Tags => self.languageClient_tags(&params),
+            REQUEST__RegisterUriHandlers => self.languageClient_registerUriHandlers(&params),
+            REQUEST__Metrics => self.languageClient_metrics(&params),
+            REQUEST__FormatChangedRanges => self.languageClient_formatChangedRanges(&params),
+            REQUEST__InlineCompletion => self.textDocument_inlineCompletion(&params),
+            REQUEST__AcceptInlineCompletion => self.languageClient_acceptInlineCompletion(&params),
+            REQUEST__WorkspaceOutline => self.languageClient_workspaceOutline(&params),
+            REQUEST__VerifyServerCommand => self.languageClient_verifyServerCommand(&params),
 
             _ => {
                 let languageId_target = if languageId.is_some() {
                     // Message from language server. No handler found.
                     let msg = format!("Message not handled: {:?}", method_call);
+                    warn!("{}", msg);
                     if method_call.method.starts_with('$') {
-                        warn!("{}", msg);
                         return Ok(Value::default());
                     } else {
-                        return Err(err_msg(msg));
+                        return Err(LCError::MethodNotFound {
+                            method: method_call.method.clone(),
+                        }.into());
                     }
                 } else {
                     // Message from vim. Proxy to language server.
-                    let (languageId_target,): (String,) =
-                        self.gather_args(&[VimVar::LanguageId], &params)?;
+                    let (languageId_target, filename): (String, String) =
+                        self.gather_args(&[VimVar::LanguageId, VimVar::Filename], &params)?;
+                    // Params are passed through as is, i.e., not mapped to notebook cells.
+                    self.ensure_not_notebook(&filename, &method_call.method)?;
+                    let languageId_target =
+                        self.route_server_key(&languageId_target, &filename, &method_call.method)?;
                     info!(
                         "Proxy message directly to language server: {:?}",
                         method_call
@@ -84,6 +116,26 @@ impl State {
                     Some(languageId_target)
                 };
 
+                let cache_kind = if method_call.method.starts_with("textDocument/semanticTokens") {
+                    Some(CacheKind::SemanticTokens)
+                } else if method_call.method == "textDocument/foldingRange" {
+                    Some(CacheKind::FoldingRanges)
+                } else {
+                    None
+                };
+                if let Some(kind) = cache_kind {
+                    let languageId_target =
+                        languageId_target.as_deref().ok_or_else(|| LCError::NotFound {
+                            what: format!("language server to proxy {} to", method_call.method),
+                        })?;
+                    return self.cached_proxy(
+                        kind,
+                        languageId_target,
+                        &method_call.method,
+                        &params,
+                    );
+                }
+
                 self.call(languageId_target.as_deref(), &method_call.method, &params)
             }
         }
@@ -101,7 +153,11 @@ impl State {
                 .user_handlers
                 .get(&notification.method)
                 .cloned()
-                .ok_or_else(|| err_msg("No user handler"))
+                .ok_or_else(|| {
+                    LCError::NotFound {
+                        what: "user handler".into(),
+                    }.into()
+                })
         });
         if let Ok(user_handler) = user_handler {
             self.call::<_, u8>(None, &user_handler, params.clone())?;
@@ -134,28 +190,37 @@ impl State {
             NOTIFICATION__HandleBufDelete => self.languageClient_handleBufDelete(&params)?,
             NOTIFICATION__HandleCursorMoved => self.languageClient_handleCursorMoved(&params)?,
             NOTIFICATION__HandleCompleteDone => self.languageClient_handleCompleteDone(&params)?,
+            NOTIFICATION__HandleCursorHold => self.languageClient_handleCursorHold(&params)?,
             NOTIFICATION__FZFSinkLocation => self.languageClient_FZFSinkLocation(&params)?,
             NOTIFICATION__FZFSinkCommand => self.languageClient_FZFSinkCommand(&params)?,
             NOTIFICATION__ClearDocumentHighlight => {
                 self.languageClient_clearDocumentHighlight(&params)?
             }
+            NOTIFICATION__DismissInlineCompletion => {
+                self.languageClient_dismissInlineCompletion(&params)?
+            }
             // Extensions by language servers.
             NOTIFICATION__LanguageStatus => self.language_status(&params)?,
             NOTIFICATION__RustBeginBuild => self.rust_handleBeginBuild(&params)?,
             NOTIFICATION__RustDiagnosticsBegin => self.rust_handleDiagnosticsBegin(&params)?,
             NOTIFICATION__RustDiagnosticsEnd => self.rust_handleDiagnosticsEnd(&params)?,
             NOTIFICATION__WindowProgress => self.window_progress(&params)?,
+            NOTIFICATION__Progress => self.progress(languageId.unwrap_or_default(), &params)?,
             NOTIFICATION__ServerExited => self.languageClient_serverExited(&params)?,
+            NOTIFICATION__GarbageCollect => self.languageClient_garbageCollect(&params)?,
+            NOTIFICATION__SaveCommandOutput => self.languageClient_saveCommandOutput(&params)?,
 
             _ => {
                 let languageId_target = if languageId.is_some() {
                     // Message from language server. No handler found.
                     let msg = format!("Message not handled: {:?}", notification);
+                    warn!("{}", msg);
                     if notification.method.starts_with('$') {
-                        warn!("{}", msg);
                         return Ok(());
                     } else {
-                        return Err(err_msg(msg));
+                        return Err(LCError::MethodNotFound {
+                            method: notification.method.clone(),
+                        }.into());
                     }
                 } else {
                     // Message from vim. Proxy to language server.
@@ -2,13 +2,85 @@ use super::*;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Errors callers, vim or the user may want to tell apart. Broken internal invariants, e.g., a
+/// poisoned lock or a disconnected channel, are left as ad hoc errors.
 #[derive(Debug, Fail)]
 pub enum LCError {
     #[fail(
         display = "Language server is not running for: {}",
         languageId
     )]
-    NoLanguageServer { languageId: String },
+    ServerNotRunning { languageId: String },
+    #[fail(
+        display = "Language client has already started for language {}.",
+        languageId
+    )]
+    ServerAlreadyStarted { languageId: String },
+    #[fail(
+        display = "No language server command found for file type: {}.",
+        languageId
+    )]
+    NoServerCommand { languageId: String },
+    #[fail(display = "Failed to find project root! path: {}", path)]
+    RootNotFound { path: String },
+    #[fail(display = "Document is not opened: {}", filename)]
+    DocumentNotOpened { filename: String },
+    #[fail(
+        display = "Position out of bounds! filename: {}, line: {}",
+        filename,
+        line
+    )]
+    PositionOutOfBounds { filename: String, line: u64 },
+    #[fail(display = "Timed out waiting for response of {}", method)]
+    RpcTimeout { method: String },
+    #[fail(display = "{}", message)]
+    ServerError { code: i64, message: String },
+    #[fail(display = "Invalid option for LanguageClient_{}: {}", option, value)]
+    InvalidOption { option: String, value: String },
+    #[fail(display = "{} is not supported in notebooks", method)]
+    NotSupportedInNotebook { method: String },
+    #[fail(display = "Invalid params: {}", message)]
+    InvalidParams { message: String },
+    #[fail(display = "Not implemented: {}", method)]
+    MethodNotFound { method: String },
+    #[fail(display = "Not found: {}", what)]
+    NotFound { what: String },
+    #[fail(display = "Invalid command: {}", message)]
+    InvalidCommand { message: String },
+    #[fail(display = "Failed to call vim function {}", function)]
+    VimCallFailed { function: String },
+    #[fail(display = "Unable to read from language server: {}", message)]
+    ServerOutputInvalid { message: String },
+    #[fail(display = "Editing cancelled: {}", filename)]
+    EditCancelled { filename: String },
+    #[fail(display = "Failed to edit {}: {}", filename, message)]
+    EditFailed { filename: String, message: String },
+}
+
+impl LCError {
+    /// Kind of error, passed to vim along with error message.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            LCError::ServerNotRunning { .. } => "ServerNotRunning",
+            LCError::ServerAlreadyStarted { .. } => "ServerAlreadyStarted",
+            LCError::NoServerCommand { .. } => "NoServerCommand",
+            LCError::RootNotFound { .. } => "RootNotFound",
+            LCError::DocumentNotOpened { .. } => "DocumentNotOpened",
+            LCError::PositionOutOfBounds { .. } => "PositionOutOfBounds",
+            LCError::RpcTimeout { .. } => "RpcTimeout",
+            LCError::ServerError { .. } => "ServerError",
+            LCError::InvalidOption { .. } => "InvalidOption",
+            LCError::NotSupportedInNotebook { .. } => "NotSupportedInNotebook",
+            LCError::InvalidParams { .. } => "InvalidParams",
+            LCError::MethodNotFound { .. } => "MethodNotFound",
+            LCError::NotFound { .. } => "NotFound",
+            LCError::InvalidCommand { .. } => "InvalidCommand",
+            LCError::VimCallFailed { .. } => "VimCallFailed",
+            LCError::ServerOutputInvalid { .. } => "ServerOutputInvalid",
+            LCError::EditCancelled { .. } => "EditCancelled",
+            LCError::EditFailed { .. } => "EditFailed",
+        }
+    }
 }
 
 // Extensions.
@@ -25,6 +97,19 @@ pub const REQUEST__NCM2OnComplete: &str = "LanguageClient_NCM2OnComplete";
 pub const REQUEST__ExplainErrorAtPoint: &str = "languageClient/explainErrorAtPoint";
 pub const REQUEST__FindLocations: &str = "languageClient/findLocations";
 pub const REQUEST__DebugInfo: &str = "languageClient/debugInfo";
+pub const REQUEST__MemoryUsage: &str = "languageClient/memoryUsage";
+pub const REQUEST__PreviewFormatting: &str = "languageClient/previewFormatting";
+pub const REQUEST__FilterReferences: &str = "languageClient/filterReferences";
+pub const REQUEST__RepeatLastCodeAction: &str = "languageClient/repeatLastCodeAction";
+pub const REQUEST__CancelProgress: &str = "languageClient/cancelProgress";
+pub const REQUEST__CompareCapabilities: &str = "languageClient/compareCapabilities";
+pub const REQUEST__Tags: &str = "languageClient/tags";
+pub const REQUEST__RegisterUriHandlers: &str = "languageClient/registerUriHandlers";
+pub const REQUEST__Metrics: &str = "languageClient/metrics";
+pub const REQUEST__FormatChangedRanges: &str = "languageClient/formatChangedRanges";
+pub const REQUEST__AcceptInlineCompletion: &str = "languageClient/acceptInlineCompletion";
+pub const REQUEST__WorkspaceOutline: &str = "languageClient/workspaceOutline";
+pub const REQUEST__VerifyServerCommand: &str = "languageClient/verifyServerCommand";
 pub const NOTIFICATION__HandleBufNewFile: &str = "languageClient/handleBufNewFile";
 pub const NOTIFICATION__HandleBufReadPost: &str = "languageClient/handleBufReadPost";
 pub const NOTIFICATION__HandleTextChanged: &str = "languageClient/handleTextChanged";
@@ -32,10 +117,26 @@ pub const NOTIFICATION__HandleBufWritePost: &str = "languageClient/handleBufWrit
 pub const NOTIFICATION__HandleBufDelete: &str = "languageClient/handleBufDelete";
 pub const NOTIFICATION__HandleCursorMoved: &str = "languageClient/handleCursorMoved";
 pub const NOTIFICATION__HandleCompleteDone: &str = "languageClient/handleCompleteDone";
+pub const REQUEST__CodeLensRefresh: &str = "workspace/codeLens/refresh";
+pub const REQUEST__SemanticTokensRefresh: &str = "workspace/semanticTokens/refresh";
+pub const REQUEST__InlayHintRefresh: &str = "workspace/inlayHint/refresh";
+pub const REQUEST__DiagnosticRefresh: &str = "workspace/diagnostic/refresh";
+pub const REQUEST__WorkDoneProgressCreate: &str = "window/workDoneProgress/create";
+pub const NOTIFICATION__Progress: &str = "$/progress";
+pub const NOTIFICATION__WorkDoneProgressCancel: &str = "window/workDoneProgress/cancel";
+pub const NOTIFICATION__NotebookDidOpen: &str = "notebookDocument/didOpen";
+pub const NOTIFICATION__NotebookDidChange: &str = "notebookDocument/didChange";
+pub const NOTIFICATION__NotebookDidSave: &str = "notebookDocument/didSave";
+pub const NOTIFICATION__NotebookDidClose: &str = "notebookDocument/didClose";
+pub const REQUEST__InlineCompletion: &str = "textDocument/inlineCompletion";
+pub const NOTIFICATION__HandleCursorHold: &str = "languageClient/handleCursorHold";
 pub const NOTIFICATION__FZFSinkLocation: &str = "LanguageClient_FZFSinkLocation";
 pub const NOTIFICATION__FZFSinkCommand: &str = "LanguageClient_FZFSinkCommand";
 pub const NOTIFICATION__ServerExited: &str = "$languageClient/serverExited";
 pub const NOTIFICATION__ClearDocumentHighlight: &str = "languageClient/clearDocumentHighlight";
+pub const NOTIFICATION__DismissInlineCompletion: &str = "languageClient/dismissInlineCompletion";
+pub const NOTIFICATION__GarbageCollect: &str = "$languageClient/garbageCollect";
+pub const NOTIFICATION__SaveCommandOutput: &str = "$languageClient/saveCommandOutput";
 
 // Extensions by language servers.
 pub const REQUEST__RustImplementations: &str = "rustDocument/implementations";
@@ -47,12 +148,28 @@ pub const NOTIFICATION__RustDiagnosticsEnd: &str = "rustDocument/diagnosticsEnd"
 pub const NOTIFICATION__WindowProgress: &str = "window/progress";
 pub const NOTIFICATION__LanguageStatus: &str = "language/status";
 pub const REQUEST__ClassFileContents: &str = "java/classFileContents";
-
-pub const CommandsClient: &[&str] = &["java.apply.workspaceEdit"];
+pub const REQUEST__DenoVirtualTextDocument: &str = "deno/virtualTextDocument";
+
+/// Command searching project for text matches, see g:LanguageClient_grepFallback.
+pub const DefaultGrepCommand: &[&str] = &[
+    "rg",
+    "--vimgrep",
+    "--fixed-strings",
+    "--word-regexp",
+    "--",
+    "{word}",
+];
+
+pub const CommandsClient: &[&str] = &[
+    "java.apply.workspaceEdit",
+    "rls.run",
+    "rust-analyzer.runSingle",
+];
 
 // Vim variable names
 pub const VIM__ServerStatus: &str = "g:LanguageClient_serverStatus";
 pub const VIM__ServerStatusMessage: &str = "g:LanguageClient_serverStatusMessage";
+pub const VIM__ServerInstances: &str = "g:LanguageClient_serverInstances";
 
 /// Thread safe read.
 pub trait SyncRead: BufRead + Sync + Send + Debug {}
@@ -100,22 +217,45 @@ pub struct State {
     #[serde(skip_serializing)]
     pub writers: HashMap<String, Box<dyn SyncWrite>>,
     pub capabilities: HashMap<String, Value>,
-    pub registrations: Vec<Registration>,
+    // Capabilities of stopped language servers, to detect changes after restart.
+    pub previous_capabilities: HashMap<String, Value>,
+    pub registrations: HashMap<String, Vec<Registration>>,
     pub roots: HashMap<String, String>,
     pub text_documents: HashMap<String, TextDocumentItem>,
     pub text_documents_metadata: HashMap<String, TextDocumentItemMetadata>,
+    // filename => notebook, for buffers synced as notebook cells.
+    pub notebook_documents: HashMap<String, NotebookDocument>,
+    pub document_generations: HashMap<String, u64>,
+    pub suspended_documents: HashSet<String>,
+    pub latencies: HashMap<String, Latency>,
+    #[serde(skip_serializing)]
+    pub metrics: Arc<Mutex<Value>>,
+    #[serde(skip_serializing)]
+    pub last_metrics_update: Instant,
+    // Address of running metrics server, and flag telling its thread to stop.
+    #[serde(skip_serializing)]
+    pub metrics_server: Option<(String, Arc<AtomicBool>)>,
+    pub last_generation: u64,
+    // server key => filename => version, documents synced to servers other than the default one.
+    pub synced_documents: HashMap<String, HashMap<String, u64>>,
     // filename => diagnostics.
     pub diagnostics: HashMap<String, Vec<Diagnostic>>,
+    // filename => source => diagnostics from g:LanguageClient_saveCommands, part of diagnostics.
+    pub external_diagnostics: HashMap<String, HashMap<String, Vec<Diagnostic>>>,
     #[serde(skip_serializing)]
     pub line_diagnostics: HashMap<(String, u64), String>,
     pub signs: HashMap<String, Vec<Sign>>,
     pub signs_placed: HashMap<String, Vec<Sign>>,
+    // Signs are defined on first placement.
+    pub signs_defined: bool,
     pub highlight_source: Option<u64>,
     pub highlights: HashMap<String, Vec<Highlight>>,
     pub highlights_placed: HashMap<String, Vec<Highlight>>,
     // TODO: make file specific.
     pub highlight_match_ids: Vec<u32>,
     pub document_highlight_source: Option<HighlightSource>,
+    // Inline completion shown as ghost text.
+    pub inline_completion: Option<InlineCompletion>,
     pub user_handlers: HashMap<String, String>,
     #[serde(skip_serializing)]
     pub watchers: HashMap<String, notify::RecommendedWatcher>,
@@ -125,15 +265,39 @@ pub struct State {
     pub is_nvim: bool,
     pub last_cursor_line: u64,
     pub last_line_diagnostic: String,
-    pub stashed_codeAction_commands: Vec<Command>,
+    // Code actions offered, with code action kind if any.
+    pub stashed_codeAction_commands: Vec<(Command, Option<String>)>,
+    pub last_codeAction: Option<(Command, Option<String>)>,
+    // Last references found, with access kind if known, for refiltering.
+    pub last_references: Vec<(Location, Option<DocumentHighlightKind>)>,
+    // server key => progress token => ongoing work done progress.
+    pub progress: HashMap<String, HashMap<String, WorkDoneProgress>>,
+    // project root => method => root or name of server chosen, see
+    // g:LanguageClient_serverPicker.
+    pub server_choices: HashMap<String, HashMap<String, String>>,
+    // filename => symbols, see g:LanguageClient_symbolIndex.
+    pub symbol_index: HashMap<String, Vec<SymbolInformation>>,
+    // Documents opened or saved since their symbols were indexed.
+    pub symbol_index_stale: HashSet<String>,
+    // filename => (document version, symbols), see LanguageClient#workspaceOutline().
+    pub outline: HashMap<String, (u64, Vec<OutlineItem>)>,
+    // Language servers waiting for warm up requests.
+    pub warmup_pending: HashSet<String>,
+    // filename => server key, files outside of project root kept away from language server.
+    pub outside_root_files: HashMap<String, String>,
+    pub response_cache: HashMap<CacheKind, HashMap<String, CacheEntry>>,
+    #[serde(skip_serializing)]
+    pub last_gc: Instant,
 
     // User settings.
-    pub serverCommands: HashMap<String, Vec<String>>,
+    pub serverCommands: HashMap<String, ServerCommand>,
+    pub additionalServerCommands: HashMap<String, HashMap<String, ServerCommand>>,
     pub autoStart: bool,
     pub selectionUI: SelectionUI,
     pub trace: Option<TraceOption>,
     pub diagnosticsEnable: bool,
     pub diagnosticsList: DiagnosticsList,
+    pub diagnosticsListLive: bool,
     pub diagnosticsDisplay: HashMap<u64, DiagnosticsDisplay>,
     pub diagnosticsSignsMax: Option<u64>,
     pub documentHighlightDisplay: HashMap<u64, DocumentHighlightDisplay>,
@@ -145,6 +309,25 @@ pub struct State {
     pub wait_output_timeout: Duration,
     pub hoverPreview: HoverPreviewOption,
     pub completionPreferTextEdit: bool,
+    pub gcInterval: Duration,
+    pub cacheTTL: HashMap<CacheKind, Duration>,
+    pub flashEditsDuration: u64,
+    pub flashEditsHighlight: String,
+    pub warmUp: HashMap<String, bool>,
+    pub importVSCodeSettings: bool,
+    pub outsideRootPolicy: OutsideRootPolicy,
+    pub pinnedServers: HashMap<String, String>,
+    pub symbolIndex: bool,
+    pub saveBeforeWorkspaceEdit: bool,
+    pub notebookCellMarkers: HashMap<String, String>,
+    pub uriHandlers: HashMap<String, UriHandler>,
+    pub suspendOnConflict: SuspendOnConflict,
+    pub metricsAddress: Option<String>,
+    pub serverPicker: bool,
+    pub changedRangesOnSave: Vec<String>,
+    pub grepFallback: Option<Vec<String>>,
+    pub saveCommands: HashMap<String, Vec<SaveCommand>>,
+    pub inlineCompletion: bool,
 
     pub loggingFile: Option<String>,
     pub loggingLevel: log::LevelFilter,
@@ -169,19 +352,32 @@ impl State {
             child_ids: HashMap::new(),
             writers: HashMap::new(),
             capabilities: HashMap::new(),
-            registrations: vec![],
+            previous_capabilities: HashMap::new(),
+            registrations: HashMap::new(),
             roots: HashMap::new(),
             text_documents: HashMap::new(),
             text_documents_metadata: HashMap::new(),
+            synced_documents: HashMap::new(),
+            notebook_documents: HashMap::new(),
+            document_generations: HashMap::new(),
+            suspended_documents: HashSet::new(),
+            latencies: HashMap::new(),
+            metrics: Arc::new(Mutex::new(Value::Null)),
+            last_metrics_update: Instant::now(),
+            metrics_server: None,
+            last_generation: 0,
             diagnostics: HashMap::new(),
+            external_diagnostics: HashMap::new(),
             line_diagnostics: HashMap::new(),
             signs: HashMap::new(),
             signs_placed: HashMap::new(),
+            signs_defined: false,
             highlight_source: None,
             highlights: HashMap::new(),
             highlights_placed: HashMap::new(),
             highlight_match_ids: Vec::new(),
             document_highlight_source: None,
+            inline_completion: None,
             user_handlers: HashMap::new(),
             watchers: HashMap::new(),
             watcher_rxs: HashMap::new(),
@@ -190,13 +386,26 @@ impl State {
             last_cursor_line: 0,
             last_line_diagnostic: " ".into(),
             stashed_codeAction_commands: vec![],
+            last_codeAction: None,
+            last_references: vec![],
+            progress: HashMap::new(),
+            server_choices: HashMap::new(),
+            symbol_index: HashMap::new(),
+            symbol_index_stale: HashSet::new(),
+            outline: HashMap::new(),
+            warmup_pending: HashSet::new(),
+            outside_root_files: HashMap::new(),
+            response_cache: HashMap::new(),
+            last_gc: Instant::now(),
 
             serverCommands: HashMap::new(),
+            additionalServerCommands: HashMap::new(),
             autoStart: true,
             selectionUI: SelectionUI::LocationList,
             trace: None,
             diagnosticsEnable: true,
             diagnosticsList: DiagnosticsList::Quickfix,
+            diagnosticsListLive: true,
             diagnosticsDisplay: DiagnosticsDisplay::default(),
             diagnosticsSignsMax: None,
             documentHighlightDisplay: DocumentHighlightDisplay::default(),
@@ -208,6 +417,29 @@ impl State {
             wait_output_timeout: Duration::from_secs(10),
             hoverPreview: HoverPreviewOption::default(),
             completionPreferTextEdit: false,
+            gcInterval: Duration::from_secs(300),
+            cacheTTL: HashMap::new(),
+            flashEditsDuration: 500,
+            flashEditsHighlight: "IncSearch".into(),
+            warmUp: HashMap::new(),
+            importVSCodeSettings: false,
+            outsideRootPolicy: OutsideRootPolicy::default(),
+            pinnedServers: HashMap::new(),
+            symbolIndex: false,
+            saveBeforeWorkspaceEdit: false,
+            notebookCellMarkers: hashmap!{
+                "python".to_owned() => "# %%".to_owned(),
+                "julia".to_owned() => "# %%".to_owned(),
+                "r".to_owned() => "# %%".to_owned(),
+            },
+            uriHandlers: UriHandler::defaults(),
+            suspendOnConflict: SuspendOnConflict::default(),
+            metricsAddress: None,
+            serverPicker: false,
+            changedRangesOnSave: vec![],
+            grepFallback: None,
+            saveCommands: HashMap::new(),
+            inlineCompletion: false,
             loggingFile: None,
             loggingLevel: log::LevelFilter::Warn,
             serverStderr: None,
@@ -238,7 +470,10 @@ impl FromStr for SelectionUI {
             "FZF" => Ok(SelectionUI::FZF),
             "QUICKFIX" => Ok(SelectionUI::Quickfix),
             "LOCATIONLIST" | "LOCATION-LIST" => Ok(SelectionUI::LocationList),
-            _ => bail!("Invalid option for LanguageClient_selectionUI: {}", s),
+            _ => Err(LCError::InvalidOption {
+                option: "selectionUI".into(),
+                value: s.into(),
+            }.into()),
         }
     }
 }
@@ -264,7 +499,10 @@ impl FromStr for HoverPreviewOption {
             "ALWAYS" => Ok(HoverPreviewOption::Always),
             "AUTO" => Ok(HoverPreviewOption::Auto),
             "NEVER" => Ok(HoverPreviewOption::Never),
-            _ => bail!("Invalid option for LanguageClient_hoverPreview: {}", s),
+            _ => Err(LCError::InvalidOption {
+                option: "hoverPreview".into(),
+                value: s.into(),
+            }.into()),
         }
     }
 }
@@ -290,7 +528,164 @@ impl FromStr for DiagnosticsList {
             "QUICKFIX" => Ok(DiagnosticsList::Quickfix),
             "LOCATION" => Ok(DiagnosticsList::Location),
             "DISABLED" => Ok(DiagnosticsList::Disabled),
-            _ => bail!("Invalid option for LanguageClient_diagnosticsList: {}", s),
+            _ => Err(LCError::InvalidOption {
+                option: "diagnosticsList".into(),
+                value: s.into(),
+            }.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CacheKind {
+    Symbols,
+    Completions,
+    SemanticTokens,
+    Hover,
+    DocumentSymbols,
+    FoldingRanges,
+}
+
+impl FromStr for CacheKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "SYMBOLS" => Ok(CacheKind::Symbols),
+            "COMPLETIONS" => Ok(CacheKind::Completions),
+            "SEMANTICTOKENS" => Ok(CacheKind::SemanticTokens),
+            "HOVER" => Ok(CacheKind::Hover),
+            "DOCUMENTSYMBOLS" => Ok(CacheKind::DocumentSymbols),
+            "FOLDINGRANGES" => Ok(CacheKind::FoldingRanges),
+            _ => Err(LCError::InvalidOption {
+                option: "cacheTTL".into(),
+                value: s.into(),
+            }.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub value: Value,
+    #[serde(skip_serializing)]
+    pub created: Instant,
+}
+
+impl CacheEntry {
+    pub fn new(value: Value) -> Self {
+        CacheEntry {
+            value,
+            created: Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReferenceAccess {
+    Read,
+    Write,
+}
+
+/// Filters applied to references before displaying them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferencesFilter {
+    /// Keep only read or write accesses.
+    #[serde(default)]
+    pub access: Option<ReferenceAccess>,
+    /// Glob patterns of paths to exclude, e.g., `*_test.go`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Keep only references under current directory.
+    #[serde(default)]
+    pub cwd: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OutsideRootPolicy {
+    /// Send file to running language server anyway.
+    Attach,
+    /// Keep language server unaware of file.
+    Ignore,
+    /// Like Ignore, additionally make the buffer read-only.
+    ReadOnly,
+    /// Start another language server instance for project root of the file.
+    Start,
+}
+
+/// Response times of requests to a language server.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Latency {
+    pub requests: u64,
+    pub last_ms: u64,
+    pub total_ms: u64,
+}
+
+impl Latency {
+    pub fn record(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        self.requests += 1;
+        self.last_ms = ms;
+        self.total_ms += ms;
+    }
+
+    pub fn average_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.requests).unwrap_or(0)
+    }
+}
+
+/// What to suspend for buffers in diff mode or with merge conflict markers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SuspendOnConflict {
+    Off,
+    /// Hide diagnostics, i.e., signs, highlights and messages.
+    Diagnostics,
+    /// Like Diagnostics, additionally stop syncing changes to language server.
+    Sync,
+}
+
+impl Default for SuspendOnConflict {
+    fn default() -> Self {
+        SuspendOnConflict::Diagnostics
+    }
+}
+
+impl FromStr for SuspendOnConflict {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "OFF" => Ok(SuspendOnConflict::Off),
+            "DIAGNOSTICS" => Ok(SuspendOnConflict::Diagnostics),
+            "SYNC" => Ok(SuspendOnConflict::Sync),
+            _ => Err(LCError::InvalidOption {
+                option: "suspendOnConflict".into(),
+                value: s.into(),
+            }.into()),
+        }
+    }
+}
+
+impl Default for OutsideRootPolicy {
+    fn default() -> Self {
+        OutsideRootPolicy::Attach
+    }
+}
+
+impl FromStr for OutsideRootPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "ATTACH" => Ok(OutsideRootPolicy::Attach),
+            "IGNORE" => Ok(OutsideRootPolicy::Ignore),
+            "READONLY" => Ok(OutsideRootPolicy::ReadOnly),
+            "START" => Ok(OutsideRootPolicy::Start),
+            _ => Err(LCError::InvalidOption {
+                option: "outsideRootPolicy".into(),
+                value: s.into(),
+            }.into()),
         }
     }
 }
@@ -619,10 +1014,19 @@ pub trait ToRpcError {
 
 impl ToRpcError for Error {
     fn to_rpc_error(&self) -> rpc::Error {
+        let err = self.downcast_ref::<LCError>();
+        let code = match err {
+            Some(LCError::InvalidParams { .. }) | Some(LCError::InvalidOption { .. }) => {
+                rpc::ErrorCode::InvalidParams
+            }
+            Some(LCError::MethodNotFound { .. }) => rpc::ErrorCode::MethodNotFound,
+            Some(LCError::ServerError { code, .. }) => rpc::ErrorCode::ServerError(*code),
+            _ => rpc::ErrorCode::InternalError,
+        };
         rpc::Error {
-            code: rpc::ErrorCode::InternalError,
+            code,
             message: self.to_string(),
-            data: None,
+            data: err.map(|err| json!({ "kind": err.kind() })),
         }
     }
 }
@@ -844,6 +1248,8 @@ pub enum VimVar {
     GotoCmd,
     Handle,
     IncludeDeclaration,
+    Fileformat,
+    Diff,
 }
 
 pub trait VimExp {
@@ -865,6 +1271,8 @@ impl VimExp for VimVar {
             VimVar::GotoCmd => "gotoCmd",
             VimVar::Handle => "handle",
             VimVar::IncludeDeclaration => "includeDeclaration",
+            VimVar::Fileformat => "fileformat",
+            VimVar::Diff => "diff",
         }.to_owned()
     }
 
@@ -877,6 +1285,8 @@ impl VimExp for VimVar {
             VimVar::Character => "LSP#character()",
             VimVar::Text => "LSP#text()",
             VimVar::Cword => "expand('<cword>')",
+            VimVar::Fileformat => "&fileformat",
+            VimVar::Diff => "&diff",
             VimVar::NewName | VimVar::GotoCmd => "v:null",
             VimVar::Handle | VimVar::IncludeDeclaration => "v:true",
         }.to_owned()
@@ -948,6 +1358,134 @@ pub enum RootMarkers {
     Map(HashMap<String, Vec<String>>),
 }
 
+/// Command to start a language server. Placeholders `{rootPath}`, `{file}` and `{tmpdir}` are
+/// expanded, no shell involved unless opted in with `shell`.
+#[derive(Debug, Eq, PartialEq, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ServerCommand {
+    Simple(Vec<String>),
+    Detailed {
+        command: Vec<String>,
+        #[serde(default)]
+        shell: bool,
+    },
+}
+
+impl ServerCommand {
+    pub fn command(&self) -> &[String] {
+        match *self {
+            ServerCommand::Simple(ref command) => command,
+            ServerCommand::Detailed { ref command, .. } => command,
+        }
+    }
+
+    pub fn shell(&self) -> bool {
+        match *self {
+            ServerCommand::Simple(_) => false,
+            ServerCommand::Detailed { shell, .. } => shell,
+        }
+    }
+}
+
+/// How to materialize content of a document by uri scheme, when opening or reading it.
+#[derive(Debug, Eq, PartialEq, Serialize, Clone)]
+pub enum UriHandler {
+    /// Plain file on disk.
+    File,
+    /// Handled by vim itself or a vim plugin, e.g., with `BufReadCmd` autocmds.
+    Vim,
+    /// Content fetched from language server with request of this method.
+    Request(String),
+    /// Content fetched by calling this vim function with the uri.
+    Function(String),
+}
+
+impl UriHandler {
+    pub fn defaults() -> HashMap<String, UriHandler> {
+        hashmap!{
+            "file".to_owned() => UriHandler::File,
+            "jdt".to_owned() => UriHandler::Request(REQUEST__ClassFileContents.to_owned()),
+            "deno".to_owned() => UriHandler::Request(REQUEST__DenoVirtualTextDocument.to_owned()),
+            "fugitive".to_owned() => UriHandler::Vim,
+            "zipfile".to_owned() => UriHandler::Vim,
+        }
+    }
+}
+
+/// External command run on save, e.g., a linter without language server, of which output is
+/// parsed into diagnostics.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveCommand {
+    pub command: Vec<String>,
+    /// Defaults to &errorformat.
+    #[serde(default)]
+    pub errorformat: Option<String>,
+}
+
+/// Response of textDocument/inlineCompletion, not yet covered by languageserver-types.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum InlineCompletionResponse {
+    List { items: Vec<InlineCompletionItem> },
+    Array(Vec<InlineCompletionItem>),
+}
+
+impl InlineCompletionResponse {
+    pub fn into_items(self) -> Vec<InlineCompletionItem> {
+        match self {
+            InlineCompletionResponse::List { items } => items,
+            InlineCompletionResponse::Array(items) => items,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineCompletionItem {
+    pub insert_text: InlineCompletionText,
+    pub range: Option<Range>,
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum InlineCompletionText {
+    Plain(String),
+    // Snippet string. Inserted with placeholders replaced by their default text.
+    Snippet { value: String },
+}
+
+impl InlineCompletionText {
+    pub fn text(&self) -> String {
+        match *self {
+            InlineCompletionText::Plain(ref value) => value.clone(),
+            InlineCompletionText::Snippet { ref value } => strip_snippet_placeholders(value),
+        }
+    }
+}
+
+/// Symbol of a document in workspace outline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutlineItem {
+    pub depth: usize,
+    pub name: String,
+    pub kind: SymbolKind,
+    pub position: Position,
+}
+
+/// Inline completion suggestion shown as ghost text after cursor.
+#[derive(Debug, Clone, Serialize)]
+pub struct InlineCompletion {
+    pub languageId: String,
+    pub filename: String,
+    pub position: Position,
+    // Text to insert at cursor, with part typed already removed.
+    pub text: String,
+    // Number of characters after cursor replaced by text.
+    pub delete: u64,
+    pub command: Option<Command>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WindowProgressParams {
     pub title: Option<String>,
@@ -956,6 +1494,27 @@ pub struct WindowProgressParams {
     pub done: Option<bool>,
 }
 
+/// Buffer synced to language server as notebook, e.g., a jupytext buffer of `.ipynb` file, with
+/// each cell starting at a marker line.
+#[derive(Debug, Clone, Serialize)]
+pub struct NotebookDocument {
+    pub version: u64,
+    pub language_id: String,
+    // (start line in buffer, text) of cells.
+    pub cells: Vec<(u64, String)>,
+    // Diagnostics of cells, in cell coordinates.
+    pub diagnostics: HashMap<usize, Vec<Diagnostic>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkDoneProgress {
+    pub token: Value,
+    pub title: String,
+    pub message: Option<String>,
+    pub percentage: Option<u64>,
+    pub cancellable: bool,
+}
+
 pub trait Filepath {
     fn filepath(&self) -> Result<PathBuf>;
 }
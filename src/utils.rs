@@ -9,6 +9,636 @@ fn test_escape_single_quote() {
     assert_eq!(escape_single_quote("my' precious"), "my'' precious");
 }
 
+/// Methods routed among language servers, with server capability providing them.
+pub const METHOD_CAPABILITIES: &[(&str, &str)] = &[
+    ("textDocument/hover", "hoverProvider"),
+    ("textDocument/completion", "completionProvider"),
+    ("textDocument/signatureHelp", "signatureHelpProvider"),
+    ("textDocument/definition", "definitionProvider"),
+    ("textDocument/typeDefinition", "typeDefinitionProvider"),
+    ("textDocument/implementation", "implementationProvider"),
+    ("textDocument/references", "referencesProvider"),
+    ("textDocument/documentHighlight", "documentHighlightProvider"),
+    ("textDocument/documentSymbol", "documentSymbolProvider"),
+    ("textDocument/codeAction", "codeActionProvider"),
+    ("textDocument/formatting", "documentFormattingProvider"),
+    ("textDocument/rangeFormatting", "documentRangeFormattingProvider"),
+    ("textDocument/rename", "renameProvider"),
+    ("textDocument/foldingRange", "foldingRangeProvider"),
+    ("textDocument/semanticTokens/full", "semanticTokensProvider"),
+];
+
+pub fn get_method_capability(method: &str) -> Option<&'static str> {
+    METHOD_CAPABILITIES
+        .iter()
+        .find(|&&(m, _)| m == method)
+        .map(|&(_, capability)| capability)
+}
+
+#[test]
+fn test_get_method_capability() {
+    assert_eq!(
+        get_method_capability("textDocument/hover"),
+        Some("hoverProvider")
+    );
+    assert_eq!(get_method_capability("workspace/symbol"), None);
+}
+
+/// Tag item, as returned by 'tagfunc'.
+pub fn location_to_tag(name: &str, location: &Location, kind: Option<&str>) -> Result<Value> {
+    let mut tag = json!({
+        "name": name,
+        "filename": location.uri.filepath()?,
+        "cmd": format!(
+            "/\\%{}l\\%{}c/",
+            location.range.start.line + 1,
+            location.range.start.character + 1
+        ),
+    });
+    if let Some(kind) = kind {
+        tag["kind"] = json!(kind);
+    }
+    Ok(tag)
+}
+
+#[test]
+fn test_location_to_tag() {
+    let location = Location {
+        uri: Url::from_file_path("/tmp/main.rs").unwrap(),
+        range: Range {
+            start: Position {
+                line: 9,
+                character: 3,
+            },
+            end: Position {
+                line: 9,
+                character: 7,
+            },
+        },
+    };
+
+    assert_eq!(
+        location_to_tag("main", &location, Some("function")).unwrap(),
+        json!({
+            "name": "main",
+            "filename": "/tmp/main.rs",
+            "cmd": "/\\%10l\\%4c/",
+            "kind": "function",
+        })
+    );
+}
+
+/// Document symbols, flat or hierarchical, as flat list of symbols.
+pub fn flatten_document_symbols(uri: &Url, result: &Value) -> Result<Vec<SymbolInformation>> {
+    fn flatten(
+        uri: &Url,
+        symbols: &[Value],
+        container_name: Option<&str>,
+        flattened: &mut Vec<SymbolInformation>,
+    ) -> Result<()> {
+        for symbol in symbols {
+            if symbol.get("location").is_some() {
+                flattened.push(serde_json::from_value(symbol.clone())?);
+                continue;
+            }
+
+            let name = symbol["name"].as_str().unwrap_or_default();
+            flattened.push(SymbolInformation {
+                name: name.to_owned(),
+                kind: serde_json::from_value(symbol["kind"].clone())?,
+                deprecated: symbol["deprecated"].as_bool(),
+                location: Location {
+                    uri: uri.clone(),
+                    range: serde_json::from_value(symbol["selectionRange"].clone())?,
+                },
+                container_name: container_name.map(ToOwned::to_owned),
+            });
+            if let Some(children) = symbol["children"].as_array() {
+                flatten(uri, children, Some(name), flattened)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut flattened = vec![];
+    if let Some(symbols) = result.as_array() {
+        flatten(uri, symbols, None, &mut flattened)?;
+    }
+    Ok(flattened)
+}
+
+/// Code action response as commands, each with its code action kind if any.
+pub fn get_code_action_commands(result: &Value) -> Result<Vec<(Command, Option<String>)>> {
+    let items: Vec<Value> = serde_json::from_value(result.clone())?;
+    items
+        .into_iter()
+        .map(|item| {
+            let kind = item["kind"].as_str().map(ToOwned::to_owned);
+            Ok((serde_json::from_value(item)?, kind))
+        })
+        .collect()
+}
+
+#[test]
+fn test_get_code_action_commands() {
+    let result = json!([
+        { "title": "Import 'foo'", "command": "java.apply", "kind": "quickfix" },
+        { "title": "Organize imports", "command": "java.apply" },
+    ]);
+    let commands = get_code_action_commands(&result).unwrap();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].0.title, "Import 'foo'");
+    assert_eq!(commands[0].1, Some("quickfix".to_owned()));
+    assert_eq!(commands[1].1, None);
+}
+
+/// Document symbols, flat or hierarchical, as outline items with nesting depth. Flat symbols
+/// are nested under the latest symbol named as their container.
+pub fn get_outline_items(result: &Value) -> Result<Vec<OutlineItem>> {
+    fn collect(symbols: &[Value], depth: usize, items: &mut Vec<OutlineItem>) -> Result<()> {
+        for symbol in symbols {
+            let name = symbol["name"].as_str().unwrap_or_default().to_owned();
+            let kind = serde_json::from_value(symbol["kind"].clone())?;
+            if symbol.get("location").is_some() {
+                let depth = symbol["containerName"]
+                    .as_str()
+                    .and_then(|container| items.iter().rev().find(|item| item.name == container))
+                    .map(|container| container.depth + 1)
+                    .unwrap_or_default();
+                let position =
+                    serde_json::from_value(symbol["location"]["range"]["start"].clone())?;
+                items.push(OutlineItem {
+                    depth,
+                    name,
+                    kind,
+                    position,
+                });
+                continue;
+            }
+
+            let position = serde_json::from_value(symbol["selectionRange"]["start"].clone())?;
+            items.push(OutlineItem {
+                depth,
+                name,
+                kind,
+                position,
+            });
+            if let Some(children) = symbol["children"].as_array() {
+                collect(children, depth + 1, items)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut items = vec![];
+    if let Some(symbols) = result.as_array() {
+        collect(symbols, 0, &mut items)?;
+    }
+    Ok(items)
+}
+
+#[test]
+fn test_get_outline_items() {
+    let range = json!({
+        "start": { "line": 1, "character": 4 },
+        "end": { "line": 1, "character": 8 },
+    });
+    let hierarchical = json!([{
+        "name": "Foo",
+        "kind": 23,
+        "range": range,
+        "selectionRange": range,
+        "children": [{
+            "name": "bar",
+            "kind": 8,
+            "range": range,
+            "selectionRange": range,
+        }],
+    }]);
+    let flat = json!([{
+        "name": "Foo",
+        "kind": 23,
+        "location": { "uri": "file:///tmp/main.rs", "range": range },
+    }, {
+        "name": "bar",
+        "kind": 8,
+        "location": { "uri": "file:///tmp/main.rs", "range": range },
+        "containerName": "Foo",
+    }]);
+
+    for result in &[hierarchical, flat] {
+        let items = get_outline_items(result).unwrap();
+        let depths: Vec<_> = items
+            .iter()
+            .map(|item| (item.name.as_str(), item.depth))
+            .collect();
+        assert_eq!(depths, vec![("Foo", 0), ("bar", 1)]);
+        assert_eq!(items[1].position, Position::new(1, 4));
+    }
+}
+
+#[test]
+fn test_flatten_document_symbols() {
+    let uri = Url::from_file_path("/tmp/main.rs").unwrap();
+    let range = json!({
+        "start": { "line": 1, "character": 4 },
+        "end": { "line": 1, "character": 8 },
+    });
+    let result = json!([{
+        "name": "Foo",
+        "kind": 23,
+        "range": range,
+        "selectionRange": range,
+        "children": [{
+            "name": "bar",
+            "kind": 8,
+            "range": range,
+            "selectionRange": range,
+        }],
+    }]);
+
+    let symbols = flatten_document_symbols(&uri, &result).unwrap();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].name, "Foo");
+    assert_eq!(symbols[1].name, "bar");
+    assert_eq!(symbols[1].container_name, Some("Foo".to_owned()));
+    assert_eq!(symbols[1].location.uri, uri);
+}
+
+/// Whether all characters of query appear in name in order, ignoring case.
+pub fn is_fuzzy_match(name: &str, query: &str) -> bool {
+    let mut chars = name.chars().flat_map(|c| c.to_lowercase());
+    query
+        .chars()
+        .flat_map(|c| c.to_lowercase())
+        .all(|q| chars.any(|c| c == q))
+}
+
+#[test]
+fn test_is_fuzzy_match() {
+    assert!(is_fuzzy_match("LanguageClient", "lc"));
+    assert!(is_fuzzy_match("LanguageClient", ""));
+    assert!(!is_fuzzy_match("LanguageClient", "cl!"));
+}
+
+/// Split text into notebook cells, each starting at a line beginning with marker. Returns start
+/// line and text of cells.
+pub fn split_notebook_cells(text: &str, marker: &str) -> Vec<(u64, String)> {
+    let mut cells: Vec<(u64, Vec<&str>)> = vec![(0, vec![])];
+    for (i, line) in text.lines().enumerate() {
+        if line.starts_with(marker) && !cells[cells.len() - 1].1.is_empty() {
+            cells.push((i as u64, vec![]));
+        }
+        let last = cells.len() - 1;
+        cells[last].1.push(line);
+    }
+    cells
+        .into_iter()
+        .map(|(start, lines)| (start, lines.join("\n")))
+        .collect()
+}
+
+#[test]
+fn test_split_notebook_cells() {
+    let text = "# %%\nimport os\n\n# %% [markdown]\n# Title\n# %%\nos.getcwd()";
+    assert_eq!(
+        split_notebook_cells(text, "# %%"),
+        vec![
+            (0, "# %%\nimport os\n".to_owned()),
+            (3, "# %% [markdown]\n# Title".to_owned()),
+            (5, "# %%\nos.getcwd()".to_owned()),
+        ]
+    );
+    assert_eq!(
+        split_notebook_cells("x = 1", "# %%"),
+        vec![(0, "x = 1".to_owned())]
+    );
+}
+
+/// URI of a notebook cell text document.
+pub fn get_notebook_cell_uri(uri: &Url, index: usize) -> Url {
+    let mut uri = uri.clone();
+    uri.set_fragment(Some(&format!("cell{}", index)));
+    uri
+}
+
+/// Index of notebook cell from URI of cell text document.
+pub fn get_notebook_cell_index(uri: &Url) -> Option<usize> {
+    uri.fragment()
+        .and_then(|fragment| fragment.trim_left_matches("cell").parse().ok())
+}
+
+#[test]
+fn test_notebook_cell_uri() {
+    let uri = Url::from_file_path("/tmp/analysis.ipynb").unwrap();
+    let cell_uri = get_notebook_cell_uri(&uri, 2);
+    assert_eq!(cell_uri.as_str(), "file:///tmp/analysis.ipynb#cell2");
+    assert_eq!(get_notebook_cell_index(&cell_uri), Some(2));
+    assert_eq!(get_notebook_cell_index(&uri), None);
+}
+
+/// Map locations and edits in cell text documents of notebooks back to notebook buffers, i.e.,
+/// URIs like `…ipynb#cell2` to the notebook URI, and lines in cell to lines in buffer.
+/// Notebooks are given as URI => start lines of cells.
+pub fn map_notebook_cell_uris(value: Value, notebooks: &HashMap<String, Vec<u64>>) -> Value {
+    fn resolve(uri: &Value, notebooks: &HashMap<String, Vec<u64>>) -> Option<(String, u64)> {
+        let mut uri = Url::parse(uri.as_str()?).ok()?;
+        let index = get_notebook_cell_index(&uri)?;
+        uri.set_fragment(None);
+        let offset = *notebooks.get(uri.as_str())?.get(index)?;
+        Some((uri.into_string(), offset))
+    }
+
+    fn walk(value: Value, notebooks: &HashMap<String, Vec<u64>>) -> Value {
+        let mut map = match value {
+            Value::Object(map) => map,
+            Value::Array(values) => {
+                return Value::Array(values.into_iter().map(|v| walk(v, notebooks)).collect())
+            }
+            value => return value,
+        };
+
+        // Location.
+        if let Some((uri, offset)) = map.get("uri").and_then(|uri| resolve(uri, notebooks)) {
+            map.insert("uri".into(), json!(uri));
+            if let Some(range) = map.remove("range") {
+                map.insert("range".into(), shift_ranges(range, offset));
+            }
+        }
+        // LocationLink.
+        if let Some((uri, offset)) = map.get("targetUri").and_then(|uri| resolve(uri, notebooks))
+        {
+            map.insert("targetUri".into(), json!(uri));
+            for key in &["targetRange", "targetSelectionRange"] {
+                if let Some(range) = map.remove(*key) {
+                    map.insert((*key).into(), shift_ranges(range, offset));
+                }
+            }
+        }
+        // TextDocumentEdit.
+        let text_document_edit = map
+            .get("textDocument")
+            .and_then(|document| resolve(&document["uri"], notebooks));
+        if let Some((uri, offset)) = text_document_edit {
+            map.insert("textDocument".into(), map["textDocument"].combine(&json!({ "uri": uri })));
+            if let Some(edits) = map.remove("edits") {
+                map.insert("edits".into(), shift_ranges(edits, offset));
+            }
+        }
+        // WorkspaceEdit changes, keyed by URI.
+        if let Some(Value::Object(changes)) = map.remove("changes") {
+            let mut mapped = serde_json::Map::new();
+            for (uri, edits) in changes {
+                let (uri, edits) = match resolve(&json!(uri), notebooks) {
+                    Some((base, offset)) => (base, shift_ranges(edits, offset)),
+                    None => (uri, edits),
+                };
+                let entry = mapped.entry(uri).or_insert_with(|| json!([]));
+                if let (Some(entry), Value::Array(edits)) = (entry.as_array_mut(), edits) {
+                    entry.extend(edits);
+                }
+            }
+            map.insert("changes".into(), Value::Object(mapped));
+        }
+
+        Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, walk(v, notebooks)))
+                .collect(),
+        )
+    }
+
+    walk(value, notebooks)
+}
+
+/// Shift lines of all ranges in value by offset, e.g., from lines in notebook cell to lines in
+/// notebook buffer.
+pub fn shift_ranges(value: Value, offset: u64) -> Value {
+    match value {
+        Value::Object(mut map) => {
+            let is_range = map.get("start").map_or(false, |p| p["line"].is_u64())
+                && map.get("end").map_or(false, |p| p["line"].is_u64());
+            if is_range {
+                for key in &["start", "end"] {
+                    let line = map[*key]["line"].as_u64().unwrap_or_default();
+                    map[*key]["line"] = json!(line + offset);
+                }
+                return Value::Object(map);
+            }
+            Value::Object(
+                map.into_iter()
+                    .map(|(k, v)| (k, shift_ranges(v, offset)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|v| shift_ranges(v, offset))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+#[test]
+fn test_map_notebook_cell_uris() {
+    let notebooks = hashmap!{
+        "file:///tmp/analysis.ipynb".to_owned() => vec![0, 10],
+    };
+    let range = |line| {
+        json!({
+            "start": { "line": line, "character": 0 },
+            "end": { "line": line, "character": 3 },
+        })
+    };
+
+    let locations = json!([
+        { "uri": "file:///tmp/analysis.ipynb#cell1", "range": range(2) },
+        { "uri": "file:///tmp/other.py", "range": range(2) },
+    ]);
+    assert_eq!(
+        map_notebook_cell_uris(locations, &notebooks),
+        json!([
+            { "uri": "file:///tmp/analysis.ipynb", "range": range(12) },
+            { "uri": "file:///tmp/other.py", "range": range(2) },
+        ])
+    );
+
+    let edit = json!({
+        "changes": {
+            "file:///tmp/analysis.ipynb#cell0": [{ "range": range(1), "newText": "x" }],
+            "file:///tmp/analysis.ipynb#cell1": [{ "range": range(1), "newText": "x" }],
+        },
+        "documentChanges": [{
+            "textDocument": { "uri": "file:///tmp/analysis.ipynb#cell1", "version": 1 },
+            "edits": [{ "range": range(0), "newText": "y" }],
+        }],
+    });
+    let mapped = map_notebook_cell_uris(edit, &notebooks);
+    let mut changes = mapped["changes"]["file:///tmp/analysis.ipynb"]
+        .as_array()
+        .cloned()
+        .unwrap();
+    changes.sort_by_key(|edit| edit["range"]["start"]["line"].as_u64());
+    assert_eq!(
+        changes,
+        vec![
+            json!({ "range": range(1), "newText": "x" }),
+            json!({ "range": range(11), "newText": "x" }),
+        ]
+    );
+    assert_eq!(
+        mapped["documentChanges"][0],
+        json!({
+            "textDocument": { "uri": "file:///tmp/analysis.ipynb", "version": 1 },
+            "edits": [{ "range": range(10), "newText": "y" }],
+        })
+    );
+}
+
+/// Scheme of an uri like `jdt://contents/...`. Plain paths, including Windows paths with drive
+/// letter, have none.
+pub fn get_uri_scheme(path: &str) -> Option<&str> {
+    let scheme = &path[..path.find(':')?];
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if valid {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_get_uri_scheme() {
+    assert_eq!(get_uri_scheme("jdt://contents/rt.jar/java.lang/String.class"), Some("jdt"));
+    assert_eq!(get_uri_scheme("fugitive:///tmp/repo/.git//0/src/main.rs"), Some("fugitive"));
+    assert_eq!(get_uri_scheme("/tmp/main.rs"), None);
+    assert_eq!(get_uri_scheme("C:\\Users\\main.rs"), None);
+}
+
+/// Quote argument to be passed through shell verbatim.
+pub fn shell_quote(s: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+#[test]
+#[cfg(not(windows))]
+fn test_shell_quote() {
+    assert_eq!(shell_quote("/tmp/my project"), "'/tmp/my project'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+}
+
+/// Expand placeholders like `{rootPath}` in server command. Values are quoted if command is
+/// going to be interpreted by shell.
+pub fn expand_command_placeholders(
+    command: &[String],
+    placeholders: &HashMap<&str, String>,
+    shell: bool,
+) -> Vec<String> {
+    command
+        .iter()
+        .map(|arg| {
+            // Single pass, so that placeholders in substituted values are kept as is.
+            let mut expanded = String::new();
+            let mut rest = arg.as_str();
+            while let Some(start) = rest.find('{') {
+                expanded.push_str(&rest[..start]);
+                rest = &rest[start..];
+                let value = rest
+                    .find('}')
+                    .and_then(|end| placeholders.get(&rest[1..end]).map(|value| (end, value)));
+                match value {
+                    Some((end, value)) => {
+                        if shell {
+                            expanded.push_str(&shell_quote(value));
+                        } else {
+                            expanded.push_str(value);
+                        }
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        expanded.push('{');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            expanded.push_str(rest);
+            expanded
+        }).collect()
+}
+
+/// Command to run command line by shell. On Windows, the command line is passed to `cmd` as is
+/// where the toolchain allows, as it has quoting of its own, see `shell_quote`.
+pub fn shell_command(command: &[String]) -> std::process::Command {
+    let command_line = command.join(" ");
+    if cfg!(windows) {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.arg("/S").arg("/C");
+        cmd_line_arg(&mut cmd, &command_line);
+        cmd
+    } else {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(command_line);
+        cmd
+    }
+}
+
+#[cfg(all(windows, has_raw_arg))]
+fn cmd_line_arg(cmd: &mut std::process::Command, command_line: &str) {
+    use std::os::windows::process::CommandExt;
+    // With /S, cmd strips the outer quotes and runs the rest verbatim.
+    cmd.raw_arg(format!("\"{}\"", command_line));
+}
+
+#[cfg(not(all(windows, has_raw_arg)))]
+fn cmd_line_arg(cmd: &mut std::process::Command, command_line: &str) {
+    cmd.arg(command_line);
+}
+
+#[test]
+fn test_expand_command_placeholders() {
+    let command: Vec<String> = vec![
+        "clangd".into(),
+        "--compile-commands-dir={rootPath}/build".into(),
+        "--log={tmpdir}/clangd.log".into(),
+        "{unknown}".into(),
+    ];
+    let placeholders = hashmap!{
+        "rootPath" => "/home/user/my project".to_owned(),
+        "tmpdir" => "/tmp".to_owned(),
+    };
+
+    assert_eq!(
+        expand_command_placeholders(&command, &placeholders, false),
+        vec![
+            "clangd",
+            "--compile-commands-dir=/home/user/my project/build",
+            "--log=/tmp/clangd.log",
+            "{unknown}",
+        ]
+    );
+
+    let command: Vec<String> = vec!["server".into(), "{file}:{rootPath}".into(), "{tmpdir".into()];
+    let placeholders = hashmap!{
+        "file" => "/tmp/{rootPath}.rs".to_owned(),
+        "rootPath" => "/tmp".to_owned(),
+        "tmpdir" => "/tmp".to_owned(),
+    };
+    assert_eq!(
+        expand_command_placeholders(&command, &placeholders, false),
+        vec!["server", "/tmp/{rootPath}.rs:/tmp", "{tmpdir"]
+    );
+}
+
 pub fn get_rootPath<'a>(
     path: &'a Path,
     languageId: &str,
@@ -50,14 +680,22 @@ pub fn get_rootPath<'a>(
                 || dir.join("Pipfile").exists()
                 || dir.join("requirements.txt").exists()
         }),
-        "c" | "cpp" => traverse_up(path, |dir| dir.join("compile_commands.json").exists()),
+        "c" | "cpp" => traverse_up(path, |dir| {
+            dir.join("compile_commands.json").exists() || dir.join(".clangd").exists()
+        }).or_else(|_| traverse_up(path, |dir| dir.join("CMakeLists.txt").exists())),
         "cs" => traverse_up(path, is_dotnet_root),
         "java" => traverse_up(path, |dir| {
             dir.join(".project").exists()
                 || dir.join("pom.xml").exists()
                 || dir.join("build.gradle").exists()
         }),
+        "kotlin" => traverse_up(path, |dir| {
+            dir.join("settings.gradle").exists() || dir.join("settings.gradle.kts").exists()
+        }).or_else(|_| traverse_up(path, |dir| dir.join("build.gradle.kts").exists())),
         "scala" => traverse_up(path, |dir| dir.join("build.sbt").exists()),
+        "elixir" => traverse_up(path, |dir| dir.join("mix.exs").exists()),
+        "ruby" => traverse_up(path, |dir| dir.join("Gemfile").exists()),
+        "zig" => traverse_up(path, |dir| dir.join("build.zig").exists()),
         "haskell" => traverse_up(path, |dir| dir.join("stack.yaml").exists())
             .or_else(|_| traverse_up(path, |dir| dir.join(".cabal").exists())),
         _ => Err(format_err!("Unknown languageId: {}", languageId)),
@@ -66,9 +704,11 @@ pub fn get_rootPath<'a>(
             dir.join(".git").exists() || dir.join(".hg").exists() || dir.join(".svn").exists()
         })
     }).or_else(|_| {
-        let parent = path
-            .parent()
-            .ok_or_else(|| format_err!("Failed to get parent dir! path: {:?}", path));
+        let parent = path.parent().ok_or_else(|| {
+            LCError::RootNotFound {
+                path: path.to_string_lossy().into_owned(),
+            }.into()
+        });
         warn!(
             "Unknown project type. Fallback to use dir as project root: {:?}",
             parent
@@ -77,6 +717,51 @@ pub fn get_rootPath<'a>(
     })
 }
 
+#[test]
+fn test_get_rootPath() {
+    let cases = [
+        ("elixir", "mix.exs", "lib/app.ex"),
+        ("ruby", "Gemfile", "lib/app/models/user.rb"),
+        ("c", "compile_commands.json", "src/main.c"),
+        ("cpp", ".clangd", "src/main.cpp"),
+        ("cpp", "CMakeLists.txt", "src/main.cpp"),
+        ("kotlin", "settings.gradle", "app/src/main/kotlin/Main.kt"),
+        ("kotlin", "build.gradle.kts", "src/main/kotlin/Main.kt"),
+        ("zig", "build.zig", "src/main.zig"),
+    ];
+
+    for (i, &(languageId, marker, file)) in cases.iter().enumerate() {
+        let root = std::env::temp_dir()
+            .join(format!("LanguageClient-rootPath-{}", std::process::id()))
+            .join(i.to_string());
+        let file = root.join(file);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(root.join(marker), "").unwrap();
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(get_rootPath(&file, languageId, &None).unwrap(), root.as_path());
+    }
+
+    std::fs::remove_dir_all(
+        std::env::temp_dir().join(format!("LanguageClient-rootPath-{}", std::process::id())),
+    ).unwrap();
+}
+
+#[test]
+fn test_get_rootPath_cmake_subdirectory() {
+    // Nearest CMakeLists.txt is not preferred over compile_commands.json further up.
+    let root = std::env::temp_dir().join(format!("LanguageClient-cmake-{}", std::process::id()));
+    let file = root.join("lib/src/lib.cpp");
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    std::fs::write(root.join("compile_commands.json"), "").unwrap();
+    std::fs::write(root.join("lib/CMakeLists.txt"), "").unwrap();
+    std::fs::write(&file, "").unwrap();
+
+    assert_eq!(get_rootPath(&file, "cpp", &None).unwrap(), root.as_path());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
 fn traverse_up<F>(path: &Path, predicate: F) -> Result<&Path>
 where
     F: Fn(&Path) -> bool,
@@ -121,7 +806,11 @@ impl<P: AsRef<Path> + std::fmt::Debug> ToUrl for P {
     fn to_url(&self) -> Result<Url> {
         Url::from_file_path(self)
             .or_else(|_| Url::from_str(&self.as_ref().to_string_lossy()))
-            .or_else(|_| Err(format_err!("Failed to convert ({:?}) to Url", self)))
+            .or_else(|_| {
+                Err(LCError::InvalidParams {
+                    message: format!("not a path or URL: {:?}", self),
+                }.into())
+            })
     }
 }
 
@@ -144,17 +833,376 @@ pub fn apply_TextEdits(lines: &[String], edits: &[TextEdit]) -> Result<Vec<Strin
             .map(|l| l.len())
             .fold(0, |acc, l| acc + l + 1 /*line ending*/)
             + end_character;
-        edits_by_index.push((start, end, &edit.new_text));
+        // Lines don't carry line endings, which are up to fileformat of the buffer.
+        edits_by_index.push((start, end, edit.new_text.replace("\r\n", "\n")));
     }
 
     let mut text = lines.join("\n");
     for (start, end, new_text) in edits_by_index {
         let start = std::cmp::min(start, text.len());
         let end = std::cmp::min(end, text.len());
-        text = String::new() + &text[..start] + new_text + &text[end..];
+        text = String::new() + &text[..start] + &new_text + &text[end..];
+    }
+
+    // Not `lines()`, which would also strip trailing `\r` of lines in files with mixed endings.
+    Ok(split_lines(&text, "\n"))
+}
+
+#[test]
+fn test_apply_TextEdit_crlf() {
+    let lines = vec!["a = 1".to_owned(), "b = 2\r".to_owned()];
+    let edit = TextEdit {
+        range: Range {
+            start: Position {
+                line: 0,
+                character: 0,
+            },
+            end: Position {
+                line: 0,
+                character: 5,
+            },
+        },
+        new_text: "a = 0\r\nc = 3".to_owned(),
+    };
+
+    assert_eq!(
+        apply_TextEdits(&lines, &[edit]).unwrap(),
+        vec!["a = 0", "c = 3", "b = 2\r"]
+    );
+}
+
+/// Quickfix entry of a line of grep output in `file:line:column:text` format, as from
+/// `rg --vimgrep`, labeled as a text match.
+pub fn parse_vimgrep_line(line: &str, cwd: &Path) -> Option<QuickfixEntry> {
+    let mut tokens = line.splitn(4, ':');
+    let filename = tokens.next()?;
+    let lnum = tokens.next()?.parse().ok()?;
+    let col = tokens.next()?.parse().ok()?;
+    let text = tokens.next().unwrap_or_default();
+    Some(QuickfixEntry {
+        filename: cwd.join(filename).to_string_lossy().into_owned(),
+        lnum,
+        col: Some(col),
+        nr: None,
+        text: Some(format!("[text match] {}", text.trim())),
+        typ: None,
+    })
+}
+
+#[test]
+fn test_parse_vimgrep_line() {
+    let line = "src/main.rs:12:5:    let state = State::new()?;";
+    let entry = parse_vimgrep_line(line, Path::new("/tmp")).unwrap();
+    assert_eq!(entry.filename, "/tmp/src/main.rs");
+    assert_eq!(entry.lnum, 12);
+    assert_eq!(entry.col, Some(5));
+    assert_eq!(
+        entry.text,
+        Some("[text match] let state = State::new()?;".to_owned())
+    );
+    assert!(parse_vimgrep_line("rg: error", Path::new("/tmp")).is_none());
+}
+
+/// Diagnostic of a quickfix entry parsed from output of external command, e.g., a linter.
+pub fn quickfix_entry_to_diagnostic(entry: &QuickfixEntry, source: &str) -> Diagnostic {
+    let line = entry.lnum.saturating_sub(1);
+    let character = entry.col.unwrap_or_default().saturating_sub(1);
+    let severity = match entry.typ.map(|c| c.to_ascii_uppercase()) {
+        Some('W') => DiagnosticSeverity::Warning,
+        Some('I') => DiagnosticSeverity::Information,
+        Some('N') | Some('H') => DiagnosticSeverity::Hint,
+        _ => DiagnosticSeverity::Error,
+    };
+    Diagnostic {
+        range: Range {
+            start: Position { line, character },
+            end: Position { line, character },
+        },
+        severity: Some(severity),
+        code: None,
+        source: Some(source.to_owned()),
+        message: entry.text.clone().unwrap_or_default(),
+        related_information: None,
+    }
+}
+
+#[test]
+fn test_quickfix_entry_to_diagnostic() {
+    let entry = QuickfixEntry {
+        filename: "/tmp/a.sh".to_owned(),
+        lnum: 3,
+        col: Some(7),
+        nr: None,
+        text: Some("Double quote to prevent globbing.".to_owned()),
+        typ: Some('w'),
+    };
+    let diagnostic = quickfix_entry_to_diagnostic(&entry, "shellcheck");
+    assert_eq!(diagnostic.range.start, Position::new(2, 6));
+    assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::Warning));
+    assert_eq!(diagnostic.source, Some("shellcheck".to_owned()));
+}
+
+/// Text of snippet as inserted without snippet support, i.e., tabstops and variables dropped and
+/// placeholders replaced with their default, or first choice.
+pub fn strip_snippet_placeholders(snippet: &str) -> String {
+    let chars: Vec<char> = snippet.chars().collect();
+    let mut idx = 0;
+    strip_snippet_until(&chars, &mut idx, false)
+}
+
+fn strip_snippet_until(chars: &[char], idx: &mut usize, nested: bool) -> String {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut text = String::new();
+    while *idx < chars.len() {
+        let c = chars[*idx];
+        *idx += 1;
+        match c {
+            '\\' if *idx < chars.len() && "$}\\".contains(chars[*idx]) => {
+                text.push(chars[*idx]);
+                *idx += 1;
+            }
+            '}' if nested => return text,
+            '$' if *idx < chars.len() && is_name(chars[*idx]) => {
+                while *idx < chars.len() && is_name(chars[*idx]) {
+                    *idx += 1;
+                }
+            }
+            '$' if chars.get(*idx) == Some(&'{') => {
+                *idx += 1;
+                while *idx < chars.len() && is_name(chars[*idx]) {
+                    *idx += 1;
+                }
+                match chars.get(*idx) {
+                    Some(':') => {
+                        *idx += 1;
+                        text.push_str(&strip_snippet_until(chars, idx, true));
+                    }
+                    Some('|') => {
+                        *idx += 1;
+                        let mut choice = String::new();
+                        while *idx < chars.len() && chars[*idx] != ',' && chars[*idx] != '|' {
+                            choice.push(chars[*idx]);
+                            *idx += 1;
+                        }
+                        while *idx < chars.len() && chars[*idx] != '}' {
+                            *idx += 1;
+                        }
+                        *idx += 1;
+                        text.push_str(&choice);
+                    }
+                    _ => {
+                        // Skip to end of tabstop or variable, transforms included.
+                        while *idx < chars.len() && chars[*idx] != '}' {
+                            *idx += 1;
+                        }
+                        *idx += 1;
+                    }
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+#[test]
+fn test_strip_snippet_placeholders() {
+    assert_eq!(strip_snippet_placeholders("foo($1)$0"), "foo()");
+    assert_eq!(
+        strip_snippet_placeholders("foo(${1:bar}, ${2:baz(${3:qux})})"),
+        "foo(bar, baz(qux))"
+    );
+    assert_eq!(strip_snippet_placeholders("${1|one,two|} ${2}"), "one ");
+    assert_eq!(strip_snippet_placeholders("\\$1 \\} $TM_FILENAME"), "$1 } ");
+    assert_eq!(strip_snippet_placeholders("a ${TM_SELECTED_TEXT:b}"), "a b");
+}
+
+/// Ghost text of inline completion replacing range of line with cursor at position, i.e., text
+/// not typed yet, and number of characters after cursor it replaces. None if the suggestion
+/// doesn't agree with what's typed before cursor or spans multiple lines of buffer.
+pub fn get_inline_completion_ghost(
+    line: &str,
+    position: Position,
+    range: Range,
+    insert_text: &str,
+) -> Option<(String, u64)> {
+    if range.start.line != position.line
+        || range.end.line != position.line
+        || range.start.character > position.character
+        || range.end.character < position.character
+    {
+        return None;
     }
 
-    Ok(text.lines().map(|l| l.to_owned()).collect())
+    let typed: String = line
+        .chars()
+        .skip(range.start.character as usize)
+        .take((position.character - range.start.character) as usize)
+        .collect();
+    if !insert_text.starts_with(&typed) {
+        return None;
+    }
+    Some((
+        insert_text[typed.len()..].to_owned(),
+        range.end.character - position.character,
+    ))
+}
+
+#[test]
+fn test_get_inline_completion_ghost() {
+    let position = Position::new(0, 8);
+    let line = "let x = foo";
+    // Suggestion starting at cursor.
+    assert_eq!(
+        get_inline_completion_ghost(line, position, Range::new(position, position), "1;"),
+        Some(("1;".to_owned(), 0))
+    );
+    // Suggestion completing word typed before cursor, replacing rest of line.
+    let range = Range::new(Position::new(0, 4), Position::new(0, 11));
+    assert_eq!(
+        get_inline_completion_ghost(line, position, range, "x = bar;"),
+        Some(("bar;".to_owned(), 3))
+    );
+    // Suggestion disagreeing with what's typed.
+    assert_eq!(
+        get_inline_completion_ghost(line, position, range, "y = bar;"),
+        None
+    );
+}
+
+/// Ranges of lines, from start to end (exclusive), added or modified in new text.
+pub fn get_changed_ranges(old: &[String], new: &[String]) -> Vec<(u64, u64)> {
+    let mut ranges = vec![];
+    let mut line = 0;
+    let mut start = None;
+    for result in diff::slice(old, new) {
+        match result {
+            diff::Result::Left(_) => {}
+            diff::Result::Right(_) => {
+                start = start.or(Some(line));
+                line += 1;
+            }
+            diff::Result::Both(_, _) => {
+                if let Some(start) = start.take() {
+                    ranges.push((start, line));
+                }
+                line += 1;
+            }
+        }
+    }
+    if let Some(start) = start {
+        ranges.push((start, line));
+    }
+    ranges
+}
+
+#[test]
+fn test_get_changed_ranges() {
+    let old: Vec<String> = ["a", "b", "c", "d", "e"].iter().map(|s| s.to_string()).collect();
+    let new: Vec<String> = ["a", "B", "c", "d", "d2", "d3"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(get_changed_ranges(&old, &new), vec![(1, 2), (4, 6)]);
+    assert_eq!(get_changed_ranges(&old, &old), Vec::<(u64, u64)>::new());
+    assert_eq!(get_changed_ranges(&[], &new), vec![(0, 6)]);
+}
+
+/// Range params of lines from start to end (exclusive), both counting from 0, as vim passes them
+/// for a visual selection from line `'<` to `'>`, counting from 1: `LSP#range_start_line()` is
+/// `'<` less one, i.e., start, and `LSP#range_end_line()` is `'>`, i.e., the last line counting
+/// from 1, which is end.
+pub fn get_line_range_params(start: u64, end: u64) -> Value {
+    json!({
+        "LSP#range_start_line()": start,
+        "LSP#range_end_line()": end,
+    })
+}
+
+#[test]
+fn test_get_line_range_params() {
+    let old: Vec<String> = ["a", "b", "c", "d", "e"].iter().map(|s| s.to_string()).collect();
+    let new: Vec<String> = ["a", "b", "C", "d", "E", "f"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let ranges = get_changed_ranges(&old, &new);
+    assert_eq!(ranges, vec![(2, 3), (4, 6)]);
+    // Selection of 3rd line only, and of 5th to last line.
+    assert_eq!(
+        get_line_range_params(ranges[0].0, ranges[0].1),
+        json!({"LSP#range_start_line()": 2, "LSP#range_end_line()": 3})
+    );
+    assert_eq!(
+        get_line_range_params(ranges[1].0, ranges[1].1),
+        json!({"LSP#range_start_line()": 4, "LSP#range_end_line()": 6})
+    );
+}
+
+/// Whether text has a complete block of merge conflict markers.
+pub fn has_conflict_markers(lines: &[String]) -> bool {
+    let mut ours = false;
+    let mut theirs = false;
+    for line in lines {
+        if line.starts_with("<<<<<<<") {
+            ours = true;
+        } else if ours && line.starts_with("=======") {
+            theirs = true;
+        } else if theirs && line.starts_with(">>>>>>>") {
+            return true;
+        }
+    }
+    false
+}
+
+#[test]
+fn test_has_conflict_markers() {
+    let lines: Vec<String> = "fn main() {\n<<<<<<< HEAD\n    0;\n=======\n    1;\n>>>>>>> topic\n}"
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect();
+    assert!(has_conflict_markers(&lines));
+
+    let lines: Vec<String> = "Title\n=======\n\nText".lines().map(ToOwned::to_owned).collect();
+    assert!(!has_conflict_markers(&lines));
+}
+
+/// Line ending of vim 'fileformat'.
+pub fn get_line_ending(fileformat: &str) -> &'static str {
+    match fileformat {
+        "dos" => "\r\n",
+        "mac" => "\r",
+        _ => "\n",
+    }
+}
+
+/// Line ending of text, `\r\n` only if used consistently, as vim's 'fileformats' detection.
+pub fn detect_line_ending(text: &str) -> &'static str {
+    let lf = text.matches('\n').count();
+    if lf > 0 && text.matches("\r\n").count() == lf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Split text into lines, like `str::lines()`, but only at given line ending.
+pub fn split_lines(text: &str, line_ending: &str) -> Vec<String> {
+    let mut lines: Vec<String> = text.split(line_ending).map(ToOwned::to_owned).collect();
+    if lines.last().map(String::is_empty) == Some(true) {
+        lines.pop();
+    }
+    lines
+}
+
+#[test]
+fn test_line_endings() {
+    assert_eq!(detect_line_ending("a\r\nb\r\n"), "\r\n");
+    assert_eq!(detect_line_ending("a\r\nb\n"), "\n");
+    assert_eq!(detect_line_ending("a"), "\n");
+    assert_eq!(split_lines("a\r\nb\r\n", "\r\n"), vec!["a", "b"]);
+    assert_eq!(split_lines("a\r\nb\n", "\n"), vec!["a\r", "b"]);
+    assert_eq!(split_lines("", "\n"), Vec::<String>::new());
 }
 
 #[test]
@@ -216,6 +1264,141 @@ fn test_apply_TextEdit_overlong_end() {
     assert_eq!(apply_TextEdits(&lines, &[edit]).unwrap(), expect);
 }
 
+/// Ranges covered by the new text once edits are applied.
+///
+/// Line shifts caused by preceding edits are accounted for. Character shifts of edits sharing a
+/// line with a preceding edit are not.
+pub fn get_TextEdits_ranges(edits: &[TextEdit]) -> Vec<Range> {
+    let mut edits: Vec<_> = edits.iter().collect();
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+
+    let mut line_delta: i64 = 0;
+    let mut ranges = vec![];
+    for edit in edits {
+        let start = Position {
+            line: (edit.range.start.line as i64 + line_delta) as u64,
+            character: edit.range.start.character,
+        };
+        let new_lines: Vec<_> = edit.new_text.split('\n').collect();
+        let last_line = new_lines.last().cloned().unwrap_or_default();
+        let end = Position {
+            line: start.line + new_lines.len() as u64 - 1,
+            character: if new_lines.len() == 1 {
+                start.character + last_line.len() as u64
+            } else {
+                last_line.len() as u64
+            },
+        };
+        line_delta += new_lines.len() as i64
+            - 1
+            - (edit.range.end.line as i64 - edit.range.start.line as i64);
+        ranges.push(Range { start, end });
+    }
+
+    ranges
+}
+
+#[test]
+fn test_get_TextEdits_ranges() {
+    let edits = vec![
+        TextEdit {
+            range: Range {
+                start: Position {
+                    line: 5,
+                    character: 0,
+                },
+                end: Position {
+                    line: 5,
+                    character: 3,
+                },
+            },
+            new_text: "bar".to_owned(),
+        },
+        TextEdit {
+            range: Range {
+                start: Position {
+                    line: 1,
+                    character: 4,
+                },
+                end: Position {
+                    line: 1,
+                    character: 4,
+                },
+            },
+            new_text: "use a;\nuse b;\n".to_owned(),
+        },
+    ];
+
+    assert_eq!(
+        get_TextEdits_ranges(&edits),
+        vec![
+            Range {
+                start: Position {
+                    line: 1,
+                    character: 4,
+                },
+                end: Position {
+                    line: 3,
+                    character: 0,
+                },
+            },
+            Range {
+                start: Position {
+                    line: 7,
+                    character: 0,
+                },
+                end: Position {
+                    line: 7,
+                    character: 3,
+                },
+            },
+        ]
+    );
+}
+
+/// Convert range into `matchaddpos()` positions, i.e., `[line, col, length]` or `[line]`, 1-based.
+pub fn get_matchaddpos_positions(range: &Range) -> Vec<Vec<u64>> {
+    if range.start.line == range.end.line {
+        if range.end.character <= range.start.character {
+            return vec![];
+        }
+        return vec![vec![
+            range.start.line + 1,
+            range.start.character + 1,
+            range.end.character - range.start.character,
+        ]];
+    }
+
+    let mut positions = vec![vec![
+        range.start.line + 1,
+        range.start.character + 1,
+        999_999, // Till the end of the line.
+    ]];
+    positions.extend((range.start.line + 1..range.end.line).map(|l| vec![l + 1]));
+    if range.end.character > 0 {
+        positions.push(vec![range.end.line + 1, 1, range.end.character]);
+    }
+    positions
+}
+
+#[test]
+fn test_get_matchaddpos_positions() {
+    let range = Range {
+        start: Position {
+            line: 1,
+            character: 4,
+        },
+        end: Position {
+            line: 3,
+            character: 2,
+        },
+    };
+    assert_eq!(
+        get_matchaddpos_positions(&range),
+        vec![vec![2, 5, 999_999], vec![3], vec![4, 1, 2]]
+    );
+}
+
 fn get_command_add_sign(sign: &Sign, filename: &str) -> String {
     format!(
         "sign place {} line={} name=LanguageClient{:?} file={}",
@@ -391,17 +1574,165 @@ fn test_expand_json_path() {
     );
 }
 
+/// Strip comments and trailing commas, which are allowed in VSCode settings files, so that the
+/// content could be parsed as plain json.
+pub fn strip_json_comments(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+
+        match (c, chars.peek().cloned()) {
+            ('"', _) => {
+                in_string = true;
+                stripped.push(c);
+            }
+            ('/', Some('/')) => {
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => stripped.push(c),
+        }
+    }
+
+    // Second pass over comment free content for trailing commas.
+    let chars: Vec<char> = stripped.chars().collect();
+    let mut result = String::with_capacity(stripped.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if next == Some(&'}') || next == Some(&']') {
+                continue;
+            }
+        }
+        result.push(c);
+    }
+
+    result
+}
+
+#[test]
+fn test_strip_json_comments() {
+    let s = r#"{
+        // Line comment.
+        "rust-analyzer.cargo.features": ["a", "b",], /* Block comment. */
+        "url": "http://example.com/*not a comment*/",
+    }"#;
+    let value: Value = serde_json::from_str(&strip_json_comments(s)).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "rust-analyzer.cargo.features": ["a", "b"],
+            "url": "http://example.com/*not a comment*/",
+        })
+    );
+}
+
+/// Settings sections in VSCode settings file known to be consumed by language servers of a
+/// language.
+pub fn get_vscode_settings_sections(languageId: &str) -> Vec<&'static str> {
+    match languageId {
+        "rust" => vec!["rust-analyzer", "rust"],
+        "go" => vec!["gopls", "go"],
+        "python" => vec!["python", "pyls", "pylsp"],
+        "javascript" | "javascript.jsx" | "typescript" | "typescript.tsx" => {
+            vec!["javascript", "typescript"]
+        }
+        "java" => vec!["java"],
+        "c" | "cpp" | "objc" | "objcpp" => vec!["clangd"],
+        "ruby" => vec!["solargraph"],
+        "css" | "scss" | "less" => vec!["css", "scss", "less"],
+        "json" => vec!["json"],
+        "yaml" => vec!["yaml"],
+        "lua" => vec!["Lua"],
+        _ => vec![],
+    }
+}
+
+/// Convert VSCode settings into workspace settings, keeping known sections only.
+pub fn import_vscode_settings(value: Value, sections: &[&str]) -> Value {
+    match value {
+        Value::Object(map) => {
+            let map = map
+                .into_iter()
+                .filter(|(k, _)| sections.contains(&k.split('.').next().unwrap_or_default()))
+                .collect();
+            expand_json_path(Value::Object(map))
+        }
+        _ => Value::Null,
+    }
+}
+
+#[test]
+fn test_import_vscode_settings() {
+    let settings = json!({
+        "editor.tabSize": 2,
+        "[rust]": {
+            "editor.formatOnSave": true
+        },
+        "rust-analyzer.cargo.features": ["a"],
+        "rust-analyzer.checkOnSave.command": "clippy",
+        "gopls.usePlaceholders": true,
+    });
+    assert_eq!(
+        import_vscode_settings(settings, &get_vscode_settings_sections("rust")),
+        json!({
+            "rust-analyzer": {
+                "cargo": {
+                    "features": ["a"]
+                },
+                "checkOnSave": {
+                    "command": "clippy"
+                }
+            }
+        })
+    );
+}
+
 pub fn vim_cmd_args_to_value(args: &[String]) -> Result<Value> {
     let mut map = serde_json::map::Map::new();
     for arg in args {
         let mut tokens: Vec<_> = arg.splitn(2, '=').collect();
         tokens.reverse();
-        let key = tokens.pop().ok_or_else(|| {
-            format_err!("Failed to parse command arguments! tokens: {:?}", tokens)
-        })?;
-        let value = tokens.pop().ok_or_else(|| {
-            format_err!("Failed to parse command arguments! tokens: {:?}", tokens)
-        })?;
+        let invalid_arg = || LCError::InvalidParams {
+            message: format!("command argument {:?}, expecting key=value", arg),
+        };
+        let key = tokens.pop().ok_or_else(invalid_arg)?;
+        let value = tokens.pop().ok_or_else(invalid_arg)?;
         let value = Value::String(value.to_owned());
         map.insert(key.to_owned(), value);
     }
@@ -472,6 +1803,159 @@ fn test_diff_value() {
     );
 }
 
+/// Top level capabilities added, removed and changed between two initialize results.
+pub fn diff_capabilities(old: &Value, new: &Value) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let is_enabled = |v: &Value| match *v {
+        Value::Null | Value::Bool(false) => false,
+        _ => true,
+    };
+
+    let mut names: Vec<String> = diff_value(&old["capabilities"], &new["capabilities"], "")
+        .keys()
+        .filter_map(|path| path.split('.').nth(1).map(|s| s.to_owned()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let (mut added, mut removed, mut changed) = (vec![], vec![], vec![]);
+    for name in names {
+        match (
+            is_enabled(&old["capabilities"][&name]),
+            is_enabled(&new["capabilities"][&name]),
+        ) {
+            (false, true) => added.push(name),
+            (true, false) => removed.push(name),
+            (true, true) => changed.push(name),
+            (false, false) => (),
+        }
+    }
+
+    (added, removed, changed)
+}
+
+#[test]
+fn test_diff_capabilities() {
+    let old = json!({
+        "capabilities": {
+            "hoverProvider": true,
+            "renameProvider": true,
+            "documentFormattingProvider": false,
+            "completionProvider": {
+                "triggerCharacters": ["."]
+            }
+        }
+    });
+    let new = json!({
+        "capabilities": {
+            "hoverProvider": true,
+            "documentFormattingProvider": true,
+            "semanticTokensProvider": {
+                "full": true
+            },
+            "completionProvider": {
+                "triggerCharacters": [".", ":"]
+            }
+        }
+    });
+    assert_eq!(
+        diff_capabilities(&old, &new),
+        (
+            vec![
+                "documentFormattingProvider".to_owned(),
+                "semanticTokensProvider".to_owned(),
+            ],
+            vec!["renameProvider".to_owned()],
+            vec!["completionProvider".to_owned()],
+        )
+    );
+}
+
+/// Apply filter to references. References of unknown access kind are kept by either access
+/// filter, as they might be reads or writes.
+pub fn filter_references(
+    references: &[(Location, Option<DocumentHighlightKind>)],
+    filter: &ReferencesFilter,
+    cwd: &Path,
+) -> Result<Vec<Location>> {
+    let exclude: std::result::Result<Vec<_>, _> = filter
+        .exclude
+        .iter()
+        .map(|p| glob::Pattern::new(p))
+        .collect();
+    let exclude = exclude?;
+
+    let mut locations = vec![];
+    for (location, kind) in references {
+        match (&filter.access, kind) {
+            (Some(ReferenceAccess::Write), Some(kind)) if *kind != DocumentHighlightKind::Write => {
+                continue
+            }
+            (Some(ReferenceAccess::Read), Some(DocumentHighlightKind::Write)) => continue,
+            _ => (),
+        }
+
+        let path = location.uri.filepath()?;
+        if filter.cwd && !path.starts_with(cwd) {
+            continue;
+        }
+        if exclude.iter().any(|p| p.matches_path(&path)) {
+            continue;
+        }
+
+        locations.push(location.clone());
+    }
+
+    Ok(locations)
+}
+
+#[test]
+fn test_filter_references() {
+    let location = |path: &str, line: u64| Location {
+        uri: Url::from_file_path(path).unwrap(),
+        range: Range {
+            start: Position { line, character: 0 },
+            end: Position { line, character: 1 },
+        },
+    };
+    let references = vec![
+        (location("/src/a.go", 0), Some(DocumentHighlightKind::Write)),
+        (location("/src/a.go", 1), Some(DocumentHighlightKind::Read)),
+        (location("/src/a_test.go", 2), None),
+        (location("/vendor/b.go", 3), None),
+    ];
+
+    let filter = ReferencesFilter::default();
+    assert_eq!(
+        filter_references(&references, &filter, Path::new("/src"))
+            .unwrap()
+            .len(),
+        4
+    );
+
+    let filter = ReferencesFilter {
+        access: Some(ReferenceAccess::Write),
+        ..ReferencesFilter::default()
+    };
+    assert_eq!(
+        filter_references(&references, &filter, Path::new("/src")).unwrap(),
+        vec![
+            location("/src/a.go", 0),
+            location("/src/a_test.go", 2),
+            location("/vendor/b.go", 3),
+        ]
+    );
+
+    let filter = ReferencesFilter {
+        access: Some(ReferenceAccess::Read),
+        exclude: vec!["*_test.go".into()],
+        cwd: true,
+    };
+    assert_eq!(
+        filter_references(&references, &filter, Path::new("/src")).unwrap(),
+        vec![location("/src/a.go", 1)]
+    );
+}
+
 pub trait Canonicalize {
     fn canonicalize(&self) -> String;
 }
@@ -502,3 +1986,55 @@ pub fn get_default_initializationOptions(languageId: &str) -> Value {
         _ => json!(Value::Null),
     }
 }
+
+/// Language id of a language server instance key, i.e., `languageId`, `languageId@rootPath` or
+/// `languageId#name@rootPath`.
+pub fn get_server_languageId(key: &str) -> &str {
+    key.split(&['@', '#'][..])
+        .next()
+        .unwrap_or_default()
+}
+
+/// Name of an additional language server from its instance key, see
+/// g:LanguageClient_additionalServerCommands.
+pub fn get_server_name(key: &str) -> Option<&str> {
+    let head = key.split('@').next().unwrap_or_default();
+    head.find('#').map(|idx| &head[idx + 1..])
+}
+
+#[test]
+fn test_get_server_languageId() {
+    assert_eq!(get_server_languageId("rust"), "rust");
+    assert_eq!(get_server_languageId("rust@/home/user/project"), "rust");
+    assert_eq!(
+        get_server_languageId("typescript#eslint@/home/user/project"),
+        "typescript"
+    );
+    assert_eq!(get_server_name("rust@/home/user/project"), None);
+    assert_eq!(
+        get_server_name("typescript#eslint@/home/user/my#project"),
+        Some("eslint")
+    );
+}
+
+/// Approximate footprint of a state store: number of entries and serialized size in bytes.
+pub fn get_store_usage<T: Serialize>(entries: usize, store: &T) -> Result<Value> {
+    Ok(json!({
+        "entries": entries,
+        "bytes": serde_json::to_vec(store)?.len(),
+    }))
+}
+
+#[test]
+fn test_get_store_usage() {
+    let store = hashmap!{
+        "a".to_owned() => "bc".to_owned(),
+    };
+    assert_eq!(
+        get_store_usage(store.len(), &store).unwrap(),
+        json!({
+            "entries": 1,
+            "bytes": 10,
+        })
+    );
+}
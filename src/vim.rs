@@ -55,7 +55,9 @@ impl State {
                 Call::MethodCall(lang_id, method_call) => {
                     let result = self.handle_method_call(lang_id.as_deref(), &method_call);
                     if let Err(ref err) = result {
-                        if err.downcast_ref::<LCError>().is_none() {
+                        if let Some(err) = err.downcast_ref::<LCError>() {
+                            warn!("Error handling message: {}", err);
+                        } else {
                             error!(
                                 "Error handling message: {}\n\nMessage: {}\n\nError: {:?}",
                                 err,
@@ -69,7 +71,9 @@ impl State {
                 Call::Notification(lang_id, notification) => {
                     let result = self.handle_notification(lang_id.as_deref(), &notification);
                     if let Err(ref err) = result {
-                        if err.downcast_ref::<LCError>().is_none() {
+                        if let Some(err) = err.downcast_ref::<LCError>() {
+                            warn!("Error handling message: {}", err);
+                        } else {
                             error!(
                                 "Error handling message: {}\n\nMessage: {}\n\nError: {:?}",
                                 err,
@@ -84,6 +88,9 @@ impl State {
             if let Err(err) = self.handle_fs_events() {
                 warn!("{:?}", err);
             }
+            if let Err(err) = self.update_metrics() {
+                warn!("Failed to update metrics: {:?}", err);
+            }
         }
     }
 
@@ -94,7 +101,7 @@ impl State {
             let writer = self
                 .writers
                 .get_mut(languageId)
-                .ok_or(LCError::NoLanguageServer {
+                .ok_or(LCError::ServerNotRunning {
                     languageId: languageId.to_owned(),
                 })?;
             write!(
@@ -116,6 +123,21 @@ impl State {
     where
         P: Serialize,
         V: DeserializeOwned,
+    {
+        let (id, start) = self.send_call(languageId, method, params)?;
+        self.wait_call(languageId, method, id, start)
+    }
+
+    /// RPC method call, without waiting for its output, so that several calls can be in flight
+    /// at once. Output is collected with `wait_call`.
+    pub fn send_call<P>(
+        &mut self,
+        languageId: Option<&str>,
+        method: &str,
+        params: P,
+    ) -> Result<(Id, Instant)>
+    where
+        P: Serialize,
     {
         self.id += 1;
         let id = self.id;
@@ -128,11 +150,50 @@ impl State {
         };
 
         let message = serde_json::to_string(&method_call)?;
+        let start = Instant::now();
         self.write(languageId, &message)?;
+        Ok((id, start))
+    }
 
-        match self.poll_output(id)? {
-            rpc::Output::Success(success) => Ok(serde_json::from_value(success.result)?),
-            rpc::Output::Failure(failure) => Err(format_err!("{}", failure.error.message)),
+    /// Wait for output of RPC method call sent with `send_call`.
+    pub fn wait_call<V>(
+        &mut self,
+        languageId: Option<&str>,
+        method: &str,
+        id: Id,
+        start: Instant,
+    ) -> Result<V>
+    where
+        V: DeserializeOwned,
+    {
+        let output = self.poll_output(id).map_err(|err| {
+            if err.downcast_ref::<RecvTimeoutError>().is_some() {
+                LCError::RpcTimeout {
+                    method: method.to_owned(),
+                }.into()
+            } else {
+                err
+            }
+        })?;
+        if let Some(languageId) = languageId {
+            self.latencies
+                .entry(languageId.to_owned())
+                .or_insert_with(Latency::default)
+                .record(start.elapsed());
+        }
+        match output {
+            rpc::Output::Success(success) => {
+                let result = if languageId.is_some() {
+                    self.map_notebook_cells(success.result)
+                } else {
+                    success.result
+                };
+                Ok(serde_json::from_value(result)?)
+            }
+            rpc::Output::Failure(failure) => Err(LCError::ServerError {
+                code: failure.error.code.code(),
+                message: failure.error.message,
+            }.into()),
         }
     }
 
@@ -192,7 +253,9 @@ impl State {
 
     pub fn command<P: Serialize + Debug>(&mut self, cmds: P) -> Result<()> {
         if self.call::<_, u8>(None, "execute", &cmds)? != 0 {
-            bail!("Failed to execute command: {:?}", cmds);
+            return Err(LCError::VimCallFailed {
+                function: "execute".into(),
+            }.into());
         }
         Ok(())
     }
@@ -243,49 +306,69 @@ impl State {
 
     pub fn setline(&mut self, lnum: u64, text: &[String]) -> Result<()> {
         if self.call::<_, u8>(None, "setline", json!([lnum, text]))? != 0 {
-            bail!("Failed to set buffer content!");
+            return Err(LCError::VimCallFailed {
+                function: "setline".into(),
+            }.into());
         }
         Ok(())
     }
 
-    pub fn edit<P: AsRef<Path>>(&mut self, goto_cmd: &Option<String>, path: P) -> Result<()> {
+    /// Open file, in current window unless goto_cmd says otherwise. Returns false if user
+    /// cancelled, e.g., instead of leaving a modified buffer.
+    pub fn edit<P: AsRef<Path>>(&mut self, goto_cmd: &Option<String>, path: P) -> Result<bool> {
         let path = path.as_ref().to_string_lossy();
 
-        let goto = goto_cmd.as_deref().unwrap_or("edit");
-        if self.call::<_, u8>(None, "s:Edit", json!([goto, path]))? != 0 {
-            bail!("Failed to edit file: {}", path);
+        // Documents of other schemes are most likely from the same language server as current
+        // buffer, e.g., `jdt://` from jdtls.
+        let origin: Option<(String, String)> = if get_uri_scheme(&path).is_some() {
+            Some(self.gather_args(&[VimVar::LanguageId, VimVar::Filename], &Value::Null)?)
+        } else {
+            None
+        };
+
+        // Without explicit command, s:Edit uses g:LanguageClient_locationOpenCommand. It returns
+        // 1 if cancelled by user, or error message if editing failed.
+        match self.call::<_, Value>(None, "s:Edit", json!([goto_cmd, path]))? {
+            Value::Number(ref n) if n.as_u64() == Some(0) => (),
+            Value::Number(ref n) if n.as_u64() == Some(1) => {
+                info!("Editing cancelled: {}", path);
+                return Ok(false);
+            }
+            result => {
+                return Err(LCError::EditFailed {
+                    filename: path.into_owned(),
+                    message: result.as_str().unwrap_or_default().to_owned(),
+                }.into())
+            }
         }
 
-        if path.starts_with("jdt://") {
-            self.command("setlocal buftype=nofile filetype=java noswapfile")?;
-
-            let result = self.java_classFileContents(&json!({
-                VimVar::LanguageId.to_key(): "java",
-                "uri": path,
-            }))?;
-            let content = match result {
-                Value::String(s) => s,
-                _ => bail!("Unexpected type: {:?}", result),
-            };
-            let lines: Vec<String> = content
-                .lines()
-                .map(std::string::ToString::to_string)
-                .collect();
-            self.setline(1, &lines)?;
+        if let Some((languageId, filename)) = origin {
+            let server_key = self.get_server_key(&languageId, &filename);
+            if let Some(lines) = self.read_uri(&server_key, &path)? {
+                self.command(&format!(
+                    "setlocal buftype=nofile filetype={} noswapfile",
+                    languageId
+                ))?;
+                self.setline(1, &lines)?;
+            }
         }
-        Ok(())
+        Ok(true)
     }
 
     pub fn setqflist(&mut self, list: &[QuickfixEntry]) -> Result<()> {
         if self.call::<_, u8>(None, "setqflist", json!([list, "r"]))? != 0 {
-            bail!("Failed to set quickfix list!");
+            return Err(LCError::VimCallFailed {
+                function: "setqflist".into(),
+            }.into());
         }
         Ok(())
     }
 
     pub fn setloclist(&mut self, list: &[QuickfixEntry]) -> Result<()> {
         if self.call::<_, u8>(None, "setloclist", json!([0, list, "r"]))? != 0 {
-            bail!("Failed to set location list!");
+            return Err(LCError::VimCallFailed {
+                function: "setloclist".into(),
+            }.into());
         }
         Ok(())
     }
@@ -350,7 +433,9 @@ pub fn loop_reader<T: BufRead>(
             if line.is_empty() {
                 count_empty_lines += 1;
                 if count_empty_lines > 5 {
-                    bail!("Unable to read from language server");
+                    return Err(LCError::ServerOutputInvalid {
+                        message: "too many empty lines".into(),
+                    }.into());
                 }
 
                 let mut buf = vec![0; content_length];
@@ -365,7 +450,9 @@ pub fn loop_reader<T: BufRead>(
                 let tokens: Vec<&str> = line.splitn(2, ':').collect();
                 let len = tokens
                     .get(1)
-                    .ok_or_else(|| format_err!("Failed to get length! tokens: {:?}", tokens))?
+                    .ok_or_else(|| LCError::ServerOutputInvalid {
+                        message: format!("header {:?}", line),
+                    })?
                     .trim();
                 content_length = usize::from_str(len)?;
             }